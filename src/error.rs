@@ -0,0 +1,55 @@
+use ethers::types::{Address, BlockNumber, TxHash, H256};
+use thiserror::Error;
+
+use crate::revert::RevertReason;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("chain does not support the `{0}` block tag")]
+    UnsupportedBlockTag(BlockNumber),
+    #[error("execution reverted: {0}")]
+    Reverted(RevertReason),
+    #[error("leaf version {found:?} is not supported, expected {expected}")]
+    UnsupportedLeafVersion { found: Option<u8>, expected: u8 },
+    #[error("block {0} not found")]
+    BlockNotFound(u64),
+    #[error("no block at or after timestamp {target}, head is at {head_timestamp}")]
+    TimestampAfterHead { target: u64, head_timestamp: u64 },
+    #[error("receipt for transaction {0:?} not found")]
+    ReceiptNotFound(TxHash),
+    #[error("batch fetch stopped after {completed} items")]
+    BatchInterrupted { completed: usize },
+    #[error("transaction {tx_hash:?} is not in block {block_number}")]
+    TransactionNotInBlock { tx_hash: TxHash, block_number: u64 },
+    #[error("receipt for transaction {0:?} has no block number")]
+    ReceiptWithoutBlock(TxHash),
+    #[error("block is still pending")]
+    PendingBlock,
+    #[error("head {head} is below the {confirmations} required confirmations")]
+    HeightBelowConfirmations { head: u64, confirmations: u64 },
+    #[error("no block up to head {head} satisfies the confirmation predicate")]
+    NothingConfirmed { head: u64 },
+    #[error("timed out waiting for block {target}, head is at {head}")]
+    WaitTimedOut { target: u64, head: u64 },
+    #[error("block {block_number} has no transaction at index {index}")]
+    TransactionIndexOutOfRange { block_number: u64, index: u64 },
+    #[error("endpoint did not answer within {0:?}")]
+    PingTimedOut(std::time::Duration),
+    #[error("transaction claims sender {claimed:?} but is signed by {recovered:?}")]
+    SenderMismatch {
+        claimed: Address,
+        recovered: Address,
+    },
+    #[error("node has no state for block {0}, an archive node is needed")]
+    StateUnavailable(u64),
+    #[error("node does not expose debug_traceTransaction")]
+    TracingUnavailable,
+    #[error("node does not support state overrides in eth_call")]
+    StateOverrideUnsupported,
+    #[error("rebuilt transactions root {computed:?} of block {block_number} does not match {expected:?}")]
+    TransactionsRootMismatch {
+        block_number: u64,
+        expected: H256,
+        computed: H256,
+    },
+}
@@ -1,15 +1,41 @@
-use std::thread::sleep;
-use std::time::Duration;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use futures::future::BoxFuture;
+use futures::Stream;
+
+use error::ClientError;
 use ethers::prelude::*;
 use ethers::providers::{Http, Provider};
+use ethers::types::transaction::eip2718::TypedTransaction;
 use ethers::types::{Block, Log, Transaction as EtherTransaction, TxHash};
-use merkle::{string_to_crypto_hash, MerkleTree, MerkleTreeProof, MerkleTreeRoot};
+use merkle::{
+    keccak256_array, string_to_crypto_hash, HashAlgo, IncrementalMerkleTree, MerkleTree,
+    MerkleTreeProof, MerkleTreeRoot,
+};
+use metrics::MetricsSnapshot;
+use revert::decode_revert;
 use serde::Serialize;
-use types::{EthereumClient, SyncData, Transaction};
+use trie::{Trie, TrieProof};
+use types::{
+    chunk_ranges, event_topic0, next_window, BlockAnchor, BlockSpec, ConfirmationPolicy,
+    ConfirmationPredicate, DefaultLeafEncoder, EthereumClient, ExportFormat, FetchedLogs,
+    LeafEncoder, LeafOptions, LogsByTransaction, MissingReceipts, RequestLimiter, RootParam,
+    StateOverride, SyncCheckpoint, SyncData, SyncStart, TaggedLogs, TraceConfig, Transaction,
+    TransactionInclusionProof, TxStatus, TxTracker, DEFAULT_LOG_CHUNK_SIZE, DEFAULT_POLL_INTERVAL,
+    DEFAULT_SYNC_WINDOW, L1_FEE_CHAINS, MAX_REORG_RETRIES, PING_TIMEOUT, TX_DROP_TIMEOUT,
+};
 
+pub mod error;
 pub mod merkle;
+pub mod metrics;
+pub mod revert;
+pub mod trie;
 pub mod types;
+pub mod watch;
 pub use ethers::*;
 
 impl EthereumClient {
@@ -22,82 +48,688 @@ impl EthereumClient {
     ) -> Self {
         let provider = Provider::<Http>::try_from(rpc).expect("Invalid provider");
 
+        Self::from_provider(provider, chain_name, chain_id, start_block, addresses)
+    }
+}
+
+impl<P: JsonRpcClient> EthereumClient<P> {
+    /// Wrap an already configured provider, e.g. one over a `RetryClient`,
+    /// `QuorumProvider` or any other custom transport.
+    pub fn from_provider(
+        provider: Provider<P>,
+        chain_name: &str,
+        chain_id: u64,
+        start_block: u64,
+        addresses: Vec<Address>,
+    ) -> Self {
         Self {
             chain_name: chain_name.to_owned(),
             chain_id,
             provider,
             start_block,
             addresses,
+            limiter: RequestLimiter::default(),
+            log_chunk_size: DEFAULT_LOG_CHUNK_SIZE,
+            missing_receipts: MissingReceipts::default(),
+            leaf_options: LeafOptions::default(),
+            metrics: Arc::default(),
+            confirmation_policy: ConfirmationPolicy::default(),
+            confirmation_predicate: None,
+            receipt_log_fallback: false,
+            max_logs_per_window: None,
+            event_topics: Vec::new(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
         }
     }
 
+    /// Events the client watches, hashed to their topic0 once here. Syncs
+    /// started with an empty `events` list filter on these.
+    pub fn with_events(mut self, events: &[&str]) -> Self {
+        self.event_topics = events.iter().map(|event| event_topic0(event)).collect();
+        self
+    }
+
+    /// Topic0 hashes of the events configured with [`with_events`](Self::with_events).
+    pub fn event_topics(&self) -> &[H256] {
+        &self.event_topics
+    }
+
+    pub fn with_leaf_options(mut self, leaf_options: LeafOptions) -> Self {
+        self.leaf_options = leaf_options;
+        self
+    }
+
+    pub fn with_missing_receipts(mut self, missing_receipts: MissingReceipts) -> Self {
+        self.missing_receipts = missing_receipts;
+        self
+    }
+
+    /// Widest block range `get_logs_filtered` asks for in one request.
+    pub fn with_log_chunk_size(mut self, size: u64) -> Self {
+        self.log_chunk_size = size.max(1);
+        self
+    }
+
+    /// Blocks a head has to be buried under before syncs read up to it.
+    pub fn with_confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmation_policy = ConfirmationPolicy::Depth(confirmations);
+        self
+    }
+
+    /// How `confirmed_head` picks the newest block syncs read.
+    pub fn with_confirmation_policy(mut self, policy: ConfirmationPolicy) -> Self {
+        self.confirmation_policy = policy;
+        self
+    }
+
+    /// Lets `predicate` decide which blocks are confirmed instead of the
+    /// `confirmation_policy`, e.g. by asking a checkpoint contract. It has to
+    /// be monotonic: once a block is confirmed, so is every block below it.
+    /// `confirmed_head` then searches for the newest confirmed block at or
+    /// below the head with O(log depth) calls. The default policy would be
+    /// `move |number| Box::pin(async move { number + gap <= head })`.
+    pub fn with_confirmation_predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(u64) -> BoxFuture<'static, bool> + Send + Sync + 'static,
+    {
+        self.confirmation_predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Lets log queries fall back to reading every block's receipts when the
+    /// node rejects `eth_getLogs` as unsupported. One block and one receipt
+    /// request per transaction replace each `eth_getLogs` call, so this is
+    /// far more expensive; every fallback scan shows up as
+    /// `receipt_log_fallback` in the metrics.
+    pub fn with_receipt_log_fallback(mut self, enabled: bool) -> Self {
+        self.receipt_log_fallback = enabled;
+        self
+    }
+
+    /// Caps the logs a `fetch_event` window returns: a window with more than
+    /// `max` logs is halved and refetched until it fits or spans one block,
+    /// and the sync advances only over the part fetched. Each oversized
+    /// response is still received once before it is dropped.
+    pub fn with_max_logs_per_window(mut self, max: usize) -> Self {
+        self.max_logs_per_window = Some(max);
+        self
+    }
+
+    /// How long a sync at the head or `wait_for_block` sleeps between polls.
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Caps the requests kept in flight by the concurrent fetch helpers.
+    pub fn with_max_concurrency(mut self, max: usize) -> Self {
+        self.limiter = RequestLimiter::new(max);
+        self
+    }
+
+    pub fn in_flight_requests(&self) -> usize {
+        self.limiter.in_flight()
+    }
+
+    /// Calls, errors and latencies of the RPC requests made so far.
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Times `request` and records it under `method`.
+    async fn observe<T>(
+        &self,
+        method: &'static str,
+        request: impl Future<Output = Result<T, ProviderError>>,
+    ) -> Result<T, ProviderError> {
+        let started = Instant::now();
+        let result = request.await;
+        self.metrics
+            .record(method, started.elapsed(), result.as_ref().err());
+        result
+    }
+
+    /// Tags a provider error with the chain it came from.
+    fn chain_error(&self, err: impl Into<anyhow::Error>) -> anyhow::Error {
+        err.into()
+            .context(format!("chain {} (id {})", self.chain_name, self.chain_id))
+    }
+
     pub async fn new_sync(&self, from: u64, events: &[&str]) -> anyhow::Result<SyncData> {
-        let cur = self.provider.get_block_number().await?.as_u64() - 3;
+        self.new_sync_for(from, events, None).await
+    }
+
+    /// `new_sync` watching `addresses` instead of the client's own set, so
+    /// one client can drive independent syncs over different contracts.
+    /// `None` uses `self.addresses`, which is never modified. Empty `events`
+    /// fall back to the client's `event_topics` when it has any.
+    pub async fn new_sync_for(
+        &self,
+        from: u64,
+        events: &[&str],
+        addresses: Option<&[Address]>,
+    ) -> anyhow::Result<SyncData> {
+        let cur = self.confirmed_head().await?;
+        let filter = Filter::new().address(addresses.unwrap_or(&self.addresses).to_vec());
+        let filter = if events.is_empty() && !self.event_topics.is_empty() {
+            filter.topic0(self.event_topics.clone())
+        } else {
+            filter.events(events)
+        };
 
         Ok(SyncData {
             cur,
             from,
-            filters: Filter::new()
-                .address(self.addresses.clone())
-                .events(events)
-                .from_block(from)
-                .to_block(cur),
-            n: 50000,
-            gap: 3,
+            filters: filter.from_block(from).to_block(cur),
+            n: DEFAULT_SYNC_WINDOW,
+            gap: match self.confirmation_policy {
+                _ if self.confirmation_predicate.is_some() => 0,
+                ConfirmationPolicy::Depth(depth) => depth,
+                ConfirmationPolicy::Safe | ConfirmationPolicy::Finalized => 0,
+            },
+            dedup: None,
         })
     }
 
+    /// `new_sync` from a starting point resolved against the chain.
+    pub async fn begin_sync(&self, start: SyncStart, events: &[&str]) -> anyhow::Result<SyncData> {
+        let from = match start {
+            SyncStart::Genesis => 0,
+            SyncStart::StartBlock => self.start_block,
+            SyncStart::Block(number) => number,
+            SyncStart::Latest => self.confirmed_head().await?,
+        };
+        self.new_sync(from, events).await
+    }
+
+    /// Fetches the next window of `sync_data` and advances it. More than `n`
+    /// blocks behind `cur` it takes the `n` blocks from `from`; otherwise it
+    /// waits `poll_interval`, takes `from..=cur` and refreshes `cur` from the
+    /// confirmed head. `from` always ends one past the last block fetched, so
+    /// consecutive windows neither overlap nor skip blocks, and a sync that
+    /// has caught up fetches nothing until the head moves. A window over
+    /// `max_logs_per_window` is shortened. Returns the logs and the next
+    /// `from` while behind, the refreshed head at the tip.
     pub async fn fetch_event(
-        &mut self,
+        &self,
         sync_data: &mut SyncData,
-    ) -> anyhow::Result<(Vec<Log>, u64)> {
-        let gap = sync_data.cur - sync_data.from;
-        let limit = if gap > sync_data.n {
-            sync_data.from + sync_data.n - 1
-        } else {
-            sleep(Duration::from_secs(10));
-            sync_data.cur
+    ) -> anyhow::Result<(FetchedLogs, u64)> {
+        let plan = next_window(sync_data.cur, sync_data.from, sync_data.n);
+        if plan.at_tip {
+            tokio::time::sleep(self.poll_interval).await;
+        }
+
+        let mut number = plan.next_from;
+        if plan.at_tip {
+            number = self.confirmed_head().await?;
+            sync_data.cur = number;
+        }
+
+        let mut logs = Vec::new();
+        if let Some((from, mut to)) = plan.range {
+            loop {
+                let window = sync_data.filters.clone().from_block(from).to_block(to);
+                let fetched = self.logs_or_fallback(&window).await?;
+                match self.max_logs_per_window {
+                    Some(max) if fetched.len() > max && to > from => to = from + (to - from) / 2,
+                    _ => {
+                        sync_data.filters = window;
+                        logs = fetched;
+                        break;
+                    }
+                }
+            }
+            sync_data.from = to + 1;
+            if !plan.at_tip {
+                number = sync_data.from;
+            }
+        }
+        let logs = FetchedLogs::split(logs);
+        let logs = match sync_data.dedup.as_mut() {
+            Some(dedup) => dedup.filter(logs),
+            None => logs,
+        };
+        Ok((logs, number))
+    }
+
+    /// Catch up from `from` to the confirmed head, then follow it: a
+    /// `new_sync` driven by [`fetch_event`](Self::fetch_event), which takes
+    /// whole windows while behind and polls every `poll_interval` at the tip.
+    ///
+    /// Each item is a window's logs with the `from` the sync continues at;
+    /// store it as a checkpoint and pass it back as `from` to resume after a
+    /// restart. A failed window is yielded as an error and retried after
+    /// `poll_interval`. Drop the stream to stop.
+    pub fn sync<'a>(
+        &'a self,
+        from: u64,
+        events: &'a [&'a str],
+    ) -> impl Stream<Item = anyhow::Result<(FetchedLogs, u64)>> + 'a {
+        futures::stream::unfold(None, move |sync: Option<SyncData>| async move {
+            let mut sync_data = match sync {
+                Some(sync_data) => sync_data,
+                None => match self.new_sync(from, events).await {
+                    Ok(sync_data) => sync_data,
+                    Err(err) => {
+                        tokio::time::sleep(self.poll_interval).await;
+                        return Some((Err(err), None));
+                    }
+                },
+            };
+            // `fetch_event` advances `from` before fetching
+            let checkpoint = sync_data.clone();
+            match self.fetch_event(&mut sync_data).await {
+                Ok((logs, _)) => Some((Ok((logs, sync_data.from)), Some(sync_data))),
+                Err(err) => {
+                    tokio::time::sleep(self.poll_interval).await;
+                    Some((Err(err), Some(checkpoint)))
+                }
+            }
+        })
+    }
+
+    /// [`sync`](Self::sync) with a [`SyncCheckpoint`] recording the last
+    /// block of every window once the consumer polls for the next item, for
+    /// clean restarts from exactly where processing stopped.
+    pub fn sync_tracked<'a>(
+        &'a self,
+        from: u64,
+        events: &'a [&'a str],
+    ) -> (
+        impl Stream<Item = anyhow::Result<(FetchedLogs, u64)>> + 'a,
+        SyncCheckpoint,
+    ) {
+        let checkpoint = SyncCheckpoint::default();
+        let tracker = checkpoint.clone();
+        let inner = Box::pin(self.sync(from, events));
+        let stream = futures::stream::unfold((inner, None::<u64>), move |(mut inner, yielded)| {
+            let tracker = tracker.clone();
+            async move {
+                // asking for the next item means the last one was handled
+                if let Some(next_from) = yielded {
+                    tracker.record(next_from.saturating_sub(1));
+                }
+                let item = inner.next().await?;
+                let yielded = item.as_ref().ok().map(|(_, next_from)| *next_from);
+                Some((item, (inner, yielded)))
+            }
+        });
+        (stream, checkpoint)
+    }
+
+    /// Newest block syncs read, per the client's `confirmation_predicate`
+    /// or else its `confirmation_policy`. With a depth, a chain younger than
+    /// that fails with [`ClientError::HeightBelowConfirmations`].
+    pub async fn confirmed_head(&self) -> anyhow::Result<u64> {
+        if let Some(predicate) = &self.confirmation_predicate {
+            let head = self.get_block_count().await?;
+            return Self::newest_confirmed(predicate, head).await;
+        }
+        let block = match self.confirmation_policy {
+            ConfirmationPolicy::Depth(confirmations) => {
+                let head = self.get_block_count().await?;
+                return head.checked_sub(confirmations).ok_or_else(|| {
+                    ClientError::HeightBelowConfirmations {
+                        head,
+                        confirmations,
+                    }
+                    .into()
+                });
+            }
+            ConfirmationPolicy::Safe => self.get_safe_block().await?,
+            ConfirmationPolicy::Finalized => self.get_finalized_block().await?,
+        };
+        Ok(block.number.ok_or(ClientError::PendingBlock)?.as_u64())
+    }
+
+    /// Newest block at or below `head` that `predicate` confirms: steps down
+    /// from the head by doubling distances until one is confirmed, then
+    /// bisects between it and the last unconfirmed probe.
+    async fn newest_confirmed(predicate: &ConfirmationPredicate, head: u64) -> anyhow::Result<u64> {
+        if predicate(head).await {
+            return Ok(head);
+        }
+        let mut unconfirmed = head;
+        let mut step = 1_u64;
+        let mut confirmed = loop {
+            if unconfirmed == 0 {
+                return Err(ClientError::NothingConfirmed { head }.into());
+            }
+            let probe = head.saturating_sub(step);
+            if predicate(probe).await {
+                break probe;
+            }
+            unconfirmed = probe;
+            step = step.saturating_mul(2);
         };
+        while unconfirmed - confirmed > 1 {
+            let mid = confirmed + (unconfirmed - confirmed) / 2;
+            if predicate(mid).await {
+                confirmed = mid;
+            } else {
+                unconfirmed = mid;
+            }
+        }
+        Ok(confirmed)
+    }
+
+    /// `fetch_event` walking backwards: windows of `n` blocks from `cur` down
+    /// to `from`, newest window first, for "most recent first" views. Logs
+    /// inside a window keep their chain order. Windows go through the same
+    /// `eth_getLogs` fallback and `max_logs_per_window` shortening as
+    /// `fetch_event`, giving up their oldest blocks. Returns `None` once
+    /// `from` has been covered; the last window leaves `from` past `cur`.
+    pub async fn fetch_event_desc(
+        &self,
+        sync_data: &mut SyncData,
+    ) -> anyhow::Result<Option<FetchedLogs>> {
+        if sync_data.cur < sync_data.from {
+            return Ok(None);
+        }
+        let high = sync_data.cur;
+        let mut low = high
+            .saturating_sub(sync_data.n.max(1) - 1)
+            .max(sync_data.from);
 
-        sync_data.filters = sync_data
-            .filters
-            .clone()
-            .from_block(sync_data.from)
-            .to_block(U64([limit]));
+        let logs = loop {
+            let window = sync_data.filters.clone().from_block(low).to_block(high);
+            let fetched = self.logs_or_fallback(&window).await?;
+            match self.max_logs_per_window {
+                Some(max) if fetched.len() > max && high > low => low = high - (high - low) / 2,
+                _ => {
+                    sync_data.filters = window;
+                    break fetched;
+                }
+            }
+        };
 
-        let mut number = limit + 1;
-        if gap > sync_data.n {
-            sync_data.from = limit + 1;
+        if low > sync_data.from {
+            sync_data.cur = low - 1;
         } else {
-            sync_data.from = limit;
-            number = self.provider.get_block_number().await?.as_u64() - sync_data.gap;
-            sync_data.cur = number;
+            sync_data.cur = low;
+            sync_data.from = low.saturating_add(1);
         }
-        let logs = self.provider.get_logs(&sync_data.filters).await?;
-        Ok((logs, number))
+        Ok(Some(FetchedLogs::split(logs)))
+    }
+
+    /// Polls every `poll_interval` until the head is at or past `target`.
+    /// Gives up with [`ClientError::WaitTimedOut`] after `timeout`.
+    pub async fn wait_for_block(&self, target: u64, timeout: Duration) -> anyhow::Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let head = self.get_block_count().await?;
+            if head >= target {
+                return Ok(());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(ClientError::WaitTimedOut { target, head }.into());
+            }
+            tokio::time::sleep(self.poll_interval.min(deadline - now)).await;
+        }
+    }
+
+    /// Round trip time of an `eth_chainId` call, through the configured
+    /// transport including any retry layer, but failing with
+    /// [`ClientError::PingTimedOut`] after [`PING_TIMEOUT`] so a dead
+    /// endpoint is reported quickly.
+    pub async fn ping(&self) -> anyhow::Result<Duration> {
+        let started = Instant::now();
+        tokio::time::timeout(
+            PING_TIMEOUT,
+            self.observe("eth_chainId", self.provider.get_chainid()),
+        )
+        .await
+        .map_err(|_| self.chain_error(ClientError::PingTimedOut(PING_TIMEOUT)))?
+        .map_err(|err| self.chain_error(err))?;
+        Ok(started.elapsed())
+    }
+
+    /// Whether [`ping`](Self::ping) succeeds.
+    pub async fn healthy(&self) -> bool {
+        self.ping().await.is_ok()
     }
 
     pub async fn get_block_count(&self) -> anyhow::Result<u64> {
-        Ok(self.provider.get_block_number().await?.as_u64())
+        Ok(self
+            .observe("eth_blockNumber", self.provider.get_block_number())
+            .await
+            .map_err(|err| self.chain_error(err))?
+            .as_u64())
     }
 
     pub async fn get_block(&self, block_number: u64) -> anyhow::Result<Option<Block<TxHash>>> {
-        Ok(self.provider.get_block(block_number).await?)
+        self.observe(
+            "eth_getBlockByNumber",
+            self.provider.get_block(block_number),
+        )
+        .await
+        .map_err(|err| self.chain_error(err))
+    }
+
+    /// Lowest block the node still serves, to clamp a sync start on pruned
+    /// or history expired nodes.
+    ///
+    /// Best-effort: nodes do not report their prune boundary, so this binary
+    /// searches `0..=head` for the first block `eth_getBlockByNumber` returns,
+    /// taking a null result or an RPC error as pruned. It assumes everything
+    /// above the boundary is available and only checks block bodies, not
+    /// state. An archive node answers after two requests. A node at head 0
+    /// without block 0 serves nothing and fails with
+    /// [`ClientError::BlockNotFound`].
+    pub async fn earliest_available_block(&self) -> anyhow::Result<u64> {
+        let head = self.get_block_count().await?;
+        if self.block_available(0).await? {
+            return Ok(0);
+        }
+        if head == 0 {
+            return Err(ClientError::BlockNotFound(0).into());
+        }
+        // everything below `low` is pruned, `high` is available
+        let (mut low, mut high) = (1, head);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.block_available(mid).await? {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        Ok(high)
+    }
+
+    async fn block_available(&self, block_number: u64) -> anyhow::Result<bool> {
+        match self
+            .observe(
+                "eth_getBlockByNumber",
+                self.provider.get_block(block_number),
+            )
+            .await
+        {
+            Ok(block) => Ok(block.is_some()),
+            Err(err) if RpcError::as_error_response(&err).is_some() => Ok(false),
+            Err(err) => Err(self.chain_error(err)),
+        }
+    }
+
+    /// Checks that blocks `from..=to` form an unbroken chain, each block's
+    /// `parent_hash` being the hash of the one before. Headers are fetched
+    /// concurrently within the concurrency limit and checked in order.
+    /// Returns the first block whose parent hash does not match, `None` when
+    /// the whole range links up.
+    pub async fn verify_block_chain(&self, from: u64, to: u64) -> anyhow::Result<Option<u64>> {
+        let blocks = futures::future::try_join_all((from..=to).map(|number| {
+            self.limiter.run(async move {
+                let block = self.get_block(number).await?;
+                anyhow::Ok(block.ok_or(ClientError::BlockNotFound(number))?)
+            })
+        }))
+        .await?;
+        Ok(blocks
+            .windows(2)
+            .zip(from + 1..)
+            .find(|(pair, _)| Some(pair[1].parent_hash) != pair[0].hash)
+            .map(|(_, number)| number))
+    }
+
+    /// Blocks in `from..=to` the node cannot produce, for auditing a
+    /// finished backfill. Headers are fetched concurrently within the
+    /// concurrency limit; a block counts as present only when the node
+    /// returns it under its own number, and the missing numbers come back in
+    /// ascending order, empty when the range is complete.
+    pub async fn verify_range_complete(&self, from: u64, to: u64) -> anyhow::Result<Vec<u64>> {
+        let numbers = futures::future::try_join_all((from..=to).map(|number| {
+            self.limiter.run(async move {
+                let block = self.get_block(number).await?;
+                anyhow::Ok(
+                    block
+                        .and_then(|block| block.number)
+                        .map(|number| number.as_u64()),
+                )
+            })
+        }))
+        .await?;
+        let seen: Vec<u64> = numbers.into_iter().flatten().collect();
+        Ok(EthereumClient::find_missing_blocks(&seen, from, to))
+    }
+
+    /// EIP-1559 base fee of `block_number`; `None` before London or on chains
+    /// without a base fee, never a made up zero.
+    pub async fn get_base_fee(&self, block_number: u64) -> anyhow::Result<Option<U256>> {
+        let block = self
+            .get_block(block_number)
+            .await?
+            .ok_or(ClientError::BlockNotFound(block_number))?;
+        Ok(block.base_fee_per_gas)
+    }
+
+    /// Block `block_number` with full transaction bodies.
+    pub async fn get_block_full(
+        &self,
+        block_number: u64,
+    ) -> anyhow::Result<Option<Block<EtherTransaction>>> {
+        self.observe(
+            "eth_getBlockByNumber",
+            self.provider.get_block_with_txs(block_number),
+        )
+        .await
+        .map_err(|err| self.chain_error(err))
+    }
+
+    /// Newest block with full transaction bodies in one
+    /// `eth_getBlockByNumber("latest", true)` call. A node that has just
+    /// started and not synced yet answers with its own head, which may be
+    /// genesis with no transactions; a null answer is an error.
+    pub async fn get_latest_block_full(&self) -> anyhow::Result<Block<EtherTransaction>> {
+        self.observe(
+            "eth_getBlockByNumber",
+            self.provider.get_block_with_txs(BlockNumber::Latest),
+        )
+        .await
+        .map_err(|err| self.chain_error(err))?
+        .ok_or_else(|| anyhow::anyhow!("node returned no latest block"))
+    }
+
+    /// The block the node is currently building, `None` when it does not
+    /// expose one. Its contents differ between nodes and change constantly.
+    pub async fn get_pending_block(&self) -> anyhow::Result<Option<Block<TxHash>>> {
+        self.observe(
+            "eth_getBlockByNumber",
+            self.provider.get_block(BlockNumber::Pending),
+        )
+        .await
+        .map_err(|err| self.chain_error(err))
+    }
+
+    /// Latest block the chain considers finalized.
+    pub async fn get_finalized_block(&self) -> anyhow::Result<Block<TxHash>> {
+        self.get_tagged_block(BlockNumber::Finalized).await
+    }
+
+    /// Latest block the chain considers safe from reorgs.
+    pub async fn get_safe_block(&self) -> anyhow::Result<Block<TxHash>> {
+        self.get_tagged_block(BlockNumber::Safe).await
+    }
+
+    async fn get_tagged_block(&self, tag: BlockNumber) -> anyhow::Result<Block<TxHash>> {
+        match self
+            .observe("eth_getBlockByNumber", self.provider.get_block(tag))
+            .await
+        {
+            Ok(Some(block)) => Ok(block),
+            Ok(None) => Err(ClientError::UnsupportedBlockTag(tag).into()),
+            // the node understood the request but rejected the tag itself
+            Err(err) if RpcError::as_error_response(&err).is_some() => Err(self.chain_error(
+                anyhow::Error::new(err).context(ClientError::UnsupportedBlockTag(tag)),
+            )),
+            Err(err) => Err(self.chain_error(err)),
+        }
+    }
+
+    /// First block between `start_block` and the head whose timestamp is at or
+    /// after `target`, found by binary search over block timestamps.
+    pub async fn block_at_timestamp(&self, target: u64) -> anyhow::Result<u64> {
+        let mut timestamps = HashMap::new();
+        let mut lo = self.start_block;
+        let mut hi = self.get_block_count().await?;
+
+        let head_timestamp = self.block_timestamp(hi, &mut timestamps).await?;
+        if head_timestamp < target {
+            return Err(ClientError::TimestampAfterHead {
+                target,
+                head_timestamp,
+            }
+            .into());
+        }
+
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.block_timestamp(mid, &mut timestamps).await? < target {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        Ok(lo)
+    }
+
+    async fn block_timestamp(
+        &self,
+        number: u64,
+        cache: &mut HashMap<u64, u64>,
+    ) -> anyhow::Result<u64> {
+        if let Some(timestamp) = cache.get(&number) {
+            return Ok(*timestamp);
+        }
+        let block = self
+            .get_block(number)
+            .await?
+            .ok_or(ClientError::BlockNotFound(number))?;
+        let timestamp = block.timestamp.as_u64();
+        cache.insert(number, timestamp);
+        Ok(timestamp)
     }
 
-    // 获取区块中的交易列表
+    /// Transactions of a block, always ordered by `transaction_index` no matter
+    /// in which order the concurrent fetches complete; merkle leaf order
+    /// depends on it.
     pub async fn get_block_transactions(
         &self,
         block_number: u64,
     ) -> anyhow::Result<Vec<EtherTransaction>> {
         let block = self.get_block(block_number).await?;
         if let Some(block) = block {
-            let mut transactions = Vec::new();
-            for tx_hash in block.transactions {
-                if let Some(tx) = self.get_transaction(tx_hash).await? {
-                    transactions.push(tx);
-                }
-            }
+            let mut transactions: Vec<_> = futures::future::try_join_all(
+                block
+                    .transactions
+                    .iter()
+                    .map(|tx_hash| self.limiter.run(self.get_transaction(*tx_hash))),
+            )
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+            transactions.sort_by_key(|tx| tx.transaction_index);
             Ok(transactions)
         } else {
             Ok(vec![])
@@ -108,110 +740,2968 @@ impl EthereumClient {
         &self,
         tx_hash: TxHash,
     ) -> anyhow::Result<Option<EtherTransaction>> {
-        Ok(self.provider.get_transaction(tx_hash).await?)
+        self.observe(
+            "eth_getTransactionByHash",
+            self.provider.get_transaction(tx_hash),
+        )
+        .await
+        .map_err(|err| self.chain_error(err))
     }
 
     pub async fn get_transaction_receipt(
         &self,
         tx_hash: TxHash,
     ) -> anyhow::Result<Option<TransactionReceipt>> {
-        Ok(self.provider.get_transaction_receipt(tx_hash).await?)
+        self.observe(
+            "eth_getTransactionReceipt",
+            self.provider.get_transaction_receipt(tx_hash),
+        )
+        .await
+        .map_err(|err| self.chain_error(err))
     }
 
-    pub async fn get_logs(&self, start_block: u64, end_block: u64) -> anyhow::Result<Vec<Log>> {
-        Ok(self
-            .provider
-            .get_logs(&Filter::new().from_block(start_block).to_block(end_block))
-            .await?)
+    /// Follows `tx_hash` until it is final, yielding each [`TxStatus`]
+    /// transition once: `Pending` while the node holds it, `Mined(block)`
+    /// when a receipt shows up and `Confirmed(block)` once the head is
+    /// `confirmations` blocks past it, after which the stream ends.
+    ///
+    /// A reorg that removes the receipt goes back to `Pending`. A
+    /// transaction the node knows nothing about (dropped from the pool, or
+    /// never propagated) for [`TX_DROP_TIMEOUT`] is reported `Dropped` and
+    /// also ends the stream; a node that does not expose its pool to
+    /// `eth_getTransactionByHash` makes every unmined transaction look like
+    /// that. Polls every `poll_interval` through the client's transport, so
+    /// a `RetryClient` retries each request; errors are yielded and polling
+    /// continues.
+    pub fn watch_transaction(
+        &self,
+        tx_hash: TxHash,
+        confirmations: u64,
+    ) -> impl Stream<Item = anyhow::Result<TxStatus>> + '_ {
+        let tracker = TxTracker::new(confirmations, TX_DROP_TIMEOUT);
+        futures::stream::unfold(
+            (tracker, VecDeque::new(), true),
+            move |(mut tracker, mut queued, mut first)| async move {
+                loop {
+                    if let Some(status) = queued.pop_front() {
+                        return Some((Ok(status), (tracker, queued, first)));
+                    }
+                    if tracker.is_done() {
+                        return None;
+                    }
+                    if !first {
+                        tokio::time::sleep(self.poll_interval).await;
+                    }
+                    first = false;
+                    match self.transaction_snapshot(tx_hash).await {
+                        Ok((known, mined, head)) => {
+                            let now = Instant::now();
+                            while let Some(status) = tracker.observe(known, mined, head, now) {
+                                queued.push_back(status);
+                            }
+                        }
+                        Err(err) => return Some((Err(err), (tracker, queued, first))),
+                    }
+                }
+            },
+        )
     }
 
-    fn data_slice<T>(datas: &Vec<T>) -> Vec<Vec<u8>>
-    where
-        T: Serialize,
-    {
-        datas
-            .iter()
-            .map(|f| string_to_crypto_hash(&serde_json::to_string(f).unwrap()).to_vec())
-            .collect()
+    /// Whether the node knows `tx_hash`, the block its receipt is in and the
+    /// head; the head is only fetched for a mined transaction.
+    async fn transaction_snapshot(
+        &self,
+        tx_hash: TxHash,
+    ) -> anyhow::Result<(bool, Option<u64>, u64)> {
+        if let Some(block) = self
+            .get_transaction_receipt(tx_hash)
+            .await?
+            .and_then(|receipt| receipt.block_number)
+        {
+            return Ok((true, Some(block.as_u64()), self.get_block_count().await?));
+        }
+        let known = self.get_transaction(tx_hash).await?.is_some();
+        Ok((known, None, 0))
     }
 
-    pub async fn get_transaction_merkle(&self, block: &Block<H256>) -> anyhow::Result<MerkleTree> {
-        let mut txs = Vec::new();
-        for x in block.transactions.clone() {
-            if let Some(receipt) = self.get_transaction_receipt(x).await? {
-                txs.push(serde_json::to_vec(&Transaction {
-                    tx_hash: serde_json::to_string(&receipt.transaction_hash)?,
-                    index: receipt.transaction_index.as_u64(),
-                    logs: receipt
-                        .logs
-                        .iter()
-                        .map(|f| serde_json::to_string(f).unwrap())
-                        .collect(),
-                    from: format!("{:?}", receipt.from),
-                    to: format!("{:?}", receipt.to),
-                    block_hash: format!("{:?}", receipt.block_hash),
-                    root: receipt.root.unwrap_or_default().to_string(),
-                    logs_bloom: receipt.logs_bloom.to_string(),
-                })?);
-            }
-        }
-        Ok(MerkleTree::build(&txs))
+    /// Total fee paid by a mined transaction: `gas_used` times the effective
+    /// gas price, plus the receipt's `l1Fee` on the [`L1_FEE_CHAINS`]. Receipts
+    /// from before London have no `effectiveGasPrice`, so the transaction's
+    /// `gasPrice` is used instead.
+    pub async fn transaction_fee(&self, tx_hash: TxHash) -> anyhow::Result<U256> {
+        let receipt = self
+            .get_transaction_receipt(tx_hash)
+            .await?
+            .ok_or(ClientError::ReceiptNotFound(tx_hash))?;
+        let gas_price = match receipt.effective_gas_price {
+            Some(price) => price,
+            None => self
+                .get_transaction(tx_hash)
+                .await?
+                .and_then(|tx| tx.gas_price)
+                .ok_or_else(|| anyhow::anyhow!("no gas price for transaction {tx_hash:?}"))?,
+        };
+        EthereumClient::receipt_fee(self.chain_id, &receipt, gas_price)
     }
 
-    pub async fn get_root_merkle(
+    /// `(block_number, transaction_index)` of a mined transaction, `None`
+    /// while it is pending or unknown. A receipt without a block number is a
+    /// node bug and fails with [`ClientError::ReceiptWithoutBlock`].
+    pub async fn locate_transaction(&self, tx_hash: TxHash) -> anyhow::Result<Option<(u64, u64)>> {
+        let Some(receipt) = self.get_transaction_receipt(tx_hash).await? else {
+            return Ok(None);
+        };
+        let block_number = receipt
+            .block_number
+            .ok_or(ClientError::ReceiptWithoutBlock(tx_hash))?;
+        Ok(Some((
+            block_number.as_u64(),
+            receipt.transaction_index.as_u64(),
+        )))
+    }
+
+    /// Logs emitted by `tx_hash`, restricted to the client's addresses and
+    /// to its `event_topics` (by topic0) when it has any. An unknown or
+    /// pending hash fails with [`ClientError::ReceiptNotFound`].
+    pub async fn get_transaction_logs(&self, tx_hash: TxHash) -> anyhow::Result<Vec<Log>> {
+        let receipt = self
+            .get_transaction_receipt(tx_hash)
+            .await?
+            .ok_or(ClientError::ReceiptNotFound(tx_hash))?;
+        Ok(receipt
+            .logs
+            .into_iter()
+            .filter(|log| self.addresses.is_empty() || self.addresses.contains(&log.address))
+            .filter(|log| {
+                self.event_topics.is_empty()
+                    || log
+                        .topics
+                        .first()
+                        .is_some_and(|topic| self.event_topics.contains(topic))
+            })
+            .collect())
+    }
+
+    /// Transaction body and receipt fetched together; `None` while the
+    /// transaction is unknown or still pending.
+    pub async fn get_transaction_full(
         &self,
-        block: &Block<H256>,
-        index: Option<u64>,
-    ) -> anyhow::Result<(MerkleTreeRoot, MerkleTreeProof, Vec<u8>)> {
-        let mut items = Vec::new();
-        let mut i = 0;
-        let mut count = 0;
-        for tx_hash in block.transactions.clone() {
-            if let Some(receipt) = self.get_transaction_receipt(tx_hash).await? {
-                items.push(serde_json::to_vec(&Transaction {
-                    tx_hash: serde_json::to_string(&receipt.transaction_hash).unwrap(),
-                    index: receipt.transaction_index.as_u64(),
-                    logs: receipt
-                        .logs
-                        .iter()
-                        .map(|f| serde_json::to_string(f).unwrap())
-                        .collect(),
-                    from: format!("{:?}", receipt.from),
-                    to: format!("{:?}", receipt.to),
-                    block_hash: format!("{:?}", receipt.block_hash),
-                    root: receipt.root.unwrap_or_default().to_string(),
-                    logs_bloom: receipt.logs_bloom.to_string(),
-                })?);
-                if let Some(c) = index {
-                    if receipt.transaction_index.as_u64() == c {
-                        i = count;
-                    }
+        tx_hash: TxHash,
+    ) -> anyhow::Result<Option<(EtherTransaction, TransactionReceipt)>> {
+        let (tx, receipt) = futures::try_join!(
+            self.limiter.run(self.get_transaction(tx_hash)),
+            self.limiter.run(self.get_transaction_receipt(tx_hash))
+        )?;
+        Ok(match (tx, receipt) {
+            (Some(tx), Some(receipt)) if tx.block_number.is_some() => Some((tx, receipt)),
+            _ => None,
+        })
+    }
+
+    /// Receipts for `hashes` in input order, fetched concurrently within the
+    /// client's concurrency limit.
+    pub async fn get_transaction_receipts(
+        &self,
+        hashes: &[TxHash],
+    ) -> anyhow::Result<Vec<Option<TransactionReceipt>>> {
+        futures::future::try_join_all(
+            hashes
+                .iter()
+                .map(|hash| self.limiter.run(self.get_transaction_receipt(*hash))),
+        )
+        .await
+    }
+
+    /// Receipts for `hashes` fetched `batch_size` at a time. `on_progress`
+    /// gets the number of receipts fetched so far after every batch. The first
+    /// failure, including a missing receipt, stops the fetch and carries a
+    /// [`ClientError::BatchInterrupted`] context with the completed count.
+    pub async fn fetch_receipts_batched(
+        &self,
+        hashes: &[TxHash],
+        batch_size: usize,
+        mut on_progress: impl FnMut(usize),
+    ) -> anyhow::Result<Vec<TransactionReceipt>> {
+        let mut receipts = Vec::with_capacity(hashes.len());
+        for batch in hashes.chunks(batch_size.max(1)) {
+            let fetched = self
+                .get_transaction_receipts(batch)
+                .await
+                .and_then(|fetched| {
+                    fetched
+                        .into_iter()
+                        .zip(batch)
+                        .map(|(receipt, hash)| {
+                            receipt.ok_or(ClientError::ReceiptNotFound(*hash).into())
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()
+                });
+            match fetched {
+                Ok(fetched) => receipts.extend(fetched),
+                Err(err) => {
+                    return Err(err.context(ClientError::BatchInterrupted {
+                        completed: receipts.len(),
+                    }))
                 }
-                count += 1;
             }
+            on_progress(receipts.len());
         }
-        let merkle = MerkleTree::build(&items);
+        Ok(receipts)
+    }
 
-        Ok((merkle.root, merkle.proofs[i].clone(), items[i].clone()))
+    /// `eth_getTransactionCount` of `address` at the `pending` tag: its mined
+    /// transactions plus those the node holds in its mempool, i.e. the next
+    /// nonce to use. Subtracting the count at `latest` gives the number of
+    /// the account's transactions still pending on this node; other nodes'
+    /// mempools may differ.
+    pub async fn get_pending_transaction_count(&self, address: Address) -> anyhow::Result<U256> {
+        self.observe(
+            "eth_getTransactionCount",
+            self.provider
+                .get_transaction_count(address, Some(BlockNumber::Pending.into())),
+        )
+        .await
+        .map_err(|err| self.chain_error(err))
     }
 
-    pub fn get_hash_merkle(
-        block: &Block<H256>,
-        transaction_hash: Option<H256>,
-    ) -> (MerkleTreeRoot, MerkleTreeProof) {
-        let mut tx_hashs = block.transactions.clone();
-        tx_hashs.push(block.transactions_root);
+    /// Node-wide mempool size from `txpool_status`: transactions ready to be
+    /// mined (`pending`) and those waiting on a nonce gap (`queued`). Geth
+    /// style nodes only; others answer with a method-not-found error.
+    pub async fn get_txpool_status(&self) -> anyhow::Result<TxpoolStatus> {
+        self.observe("txpool_status", self.provider.txpool_status())
+            .await
+            .map_err(|err| self.chain_error(err))
+    }
 
-        let index = if let Some(hx) = transaction_hash {
-            tx_hashs
-                .iter()
-                .position(|h| h == &hx)
-                .expect("Transaction hash not found")
-        } else {
-            0
-        };
-        let hash_items = Self::data_slice(&tx_hashs);
-        let merkle = MerkleTree::build(&hash_items);
-        (merkle.root, merkle.proofs[index].clone())
+    /// Balances of `addresses` in input order, all read at `block` so the
+    /// snapshot is consistent while the chain advances. Fetched concurrently
+    /// within the client's concurrency limit.
+    pub async fn get_balances(
+        &self,
+        addresses: &[Address],
+        block: u64,
+    ) -> anyhow::Result<Vec<U256>> {
+        futures::future::try_join_all(addresses.iter().map(|address| {
+            self.limiter.run(async move {
+                self.observe(
+                    "eth_getBalance",
+                    self.provider.get_balance(*address, Some(block.into())),
+                )
+                .await
+                .map_err(|err| self.chain_error(err))
+            })
+        }))
+        .await
+    }
+
+    pub async fn get_code(&self, address: Address, block: Option<u64>) -> anyhow::Result<Bytes> {
+        self.observe(
+            "eth_getCode",
+            self.provider.get_code(address, block.map(BlockId::from)),
+        )
+        .await
+        .map_err(|err| self.chain_error(err))
+    }
+
+    /// Value of storage `slot` of `address` at each of `blocks`, fetched
+    /// concurrently within the concurrency limit and sorted by block number.
+    ///
+    /// Anything older than the last few hundred blocks needs an archive
+    /// node; a node that has pruned the state of a block fails with
+    /// [`ClientError::StateUnavailable`] for that block.
+    pub async fn get_storage_history(
+        &self,
+        address: Address,
+        slot: H256,
+        blocks: &[u64],
+    ) -> anyhow::Result<Vec<(u64, H256)>> {
+        let mut history = futures::future::try_join_all(blocks.iter().map(|&block| {
+            self.limiter.run(async move {
+                let value = self
+                    .observe(
+                        "eth_getStorageAt",
+                        self.provider
+                            .get_storage_at(address, slot, Some(BlockId::from(block))),
+                    )
+                    .await
+                    .map_err(|err| {
+                        if Self::state_unavailable(&err) {
+                            self.chain_error(
+                                anyhow::Error::new(err)
+                                    .context(ClientError::StateUnavailable(block)),
+                            )
+                        } else {
+                            self.chain_error(err)
+                        }
+                    })?;
+                anyhow::Ok((block, value))
+            })
+        }))
+        .await?;
+        history.sort_by_key(|(block, _)| *block);
+        Ok(history)
+    }
+
+    /// How full nodes refuse state they have pruned: Geth and Nethermind
+    /// report a "missing trie node", Erigon and Reth that the historical
+    /// state is not available or was pruned.
+    fn state_unavailable(err: &ProviderError) -> bool {
+        RpcError::as_error_response(err).is_some_and(|response| {
+            let message = response.message.to_lowercase();
+            message.contains("missing trie node")
+                || (message.contains("state")
+                    && (message.contains("not available")
+                        || message.contains("unavailable")
+                        || message.contains("pruned")))
+        })
+    }
+
+    /// `eth_getProof` for `address` and its storage `slots`; check the
+    /// account part with [`trie::verify_account_proof`].
+    pub async fn get_proof(
+        &self,
+        address: Address,
+        slots: Vec<H256>,
+        block: Option<u64>,
+    ) -> anyhow::Result<EIP1186ProofResponse> {
+        self.observe(
+            "eth_getProof",
+            self.provider
+                .get_proof(address, slots, block.map(BlockId::from)),
+        )
+        .await
+        .map_err(|err| self.chain_error(err))
+    }
+
+    /// Keccak hash of the deployed bytecode, i.e. the `EXTCODEHASH` of the
+    /// account. Accounts without code hash to `keccak256([])`
+    /// (`0xc5d2…a470`), not zero.
+    pub async fn get_code_hash(
+        &self,
+        address: Address,
+        block: Option<u64>,
+    ) -> anyhow::Result<H256> {
+        let code = self.get_code(address, block).await?;
+        Ok(H256(keccak256_array(&code)))
+    }
+
+    pub async fn get_code_size(
+        &self,
+        address: Address,
+        block: Option<u64>,
+    ) -> anyhow::Result<usize> {
+        Ok(self.get_code(address, block).await?.len())
+    }
+
+    /// `eth_call`; a revert surfaces as [`ClientError::Reverted`] with the decoded reason.
+    pub async fn call(&self, tx: &TypedTransaction, block: Option<u64>) -> anyhow::Result<Bytes> {
+        self.observe("eth_call", self.provider.call(tx, block.map(BlockId::from)))
+            .await
+            .map_err(|err| self.chain_error(Self::revert_error(err)))
+    }
+
+    /// `eth_call` of `calldata` against `to` with the state in `overrides`
+    /// swapped in for the call, as Geth and Erigon support it. A node that
+    /// rejects the override argument fails with
+    /// [`ClientError::StateOverrideUnsupported`] instead of running the call
+    /// on unmodified state; a revert surfaces as [`ClientError::Reverted`].
+    pub async fn call_with_overrides(
+        &self,
+        to: Address,
+        calldata: Bytes,
+        overrides: &StateOverride,
+        block: Option<u64>,
+    ) -> anyhow::Result<Bytes> {
+        let tx: TypedTransaction = TransactionRequest::new().to(to).data(calldata).into();
+        let block = block.map_or(BlockNumber::Latest, BlockNumber::from);
+        self.observe(
+            "eth_call",
+            self.provider.request("eth_call", (tx, block, overrides)),
+        )
+        .await
+        .map_err(|err| {
+            if Self::overrides_unsupported(&err) {
+                self.chain_error(
+                    anyhow::Error::new(err).context(ClientError::StateOverrideUnsupported),
+                )
+            } else {
+                self.chain_error(Self::revert_error(err))
+            }
+        })
+    }
+
+    /// Nodes without overrides either lack the method variant or reject the
+    /// third parameter, e.g. Geth's "too many arguments, want at most 2".
+    fn overrides_unsupported(err: &ProviderError) -> bool {
+        Self::is_unsupported(err)
+            || RpcError::as_error_response(err)
+                .is_some_and(|response| response.message.contains("too many arguments"))
+    }
+
+    /// `debug_traceTransaction` of `tx_hash` with `tracer`, as the node's
+    /// JSON since every tracer has its own schema. Most public endpoints
+    /// keep the `debug` namespace disabled; that fails with
+    /// [`ClientError::TracingUnavailable`].
+    pub async fn trace_transaction(
+        &self,
+        tx_hash: TxHash,
+        tracer: TraceConfig,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.observe(
+            "debug_traceTransaction",
+            self.provider
+                .request("debug_traceTransaction", (tx_hash, tracer.to_params())),
+        )
+        .await
+        .map_err(|err| {
+            if Self::tracing_unavailable(&err) {
+                self.chain_error(anyhow::Error::new(err).context(ClientError::TracingUnavailable))
+            } else {
+                self.chain_error(err)
+            }
+        })
+    }
+
+    /// Geth answers a disabled namespace with -32601 and "the method
+    /// debug_traceTransaction does not exist/is not available".
+    fn tracing_unavailable(err: &ProviderError) -> bool {
+        Self::is_unsupported(err)
+            || RpcError::as_error_response(err)
+                .is_some_and(|response| response.message.contains("is not available"))
+    }
+
+    /// `eth_estimateGas`; a revert surfaces as [`ClientError::Reverted`] with the decoded reason.
+    pub async fn estimate_gas(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<u64>,
+    ) -> anyhow::Result<U256> {
+        self.observe(
+            "eth_estimateGas",
+            self.provider.estimate_gas(tx, block.map(BlockId::from)),
+        )
+        .await
+        .map_err(|err| self.chain_error(Self::revert_error(err)))
+    }
+
+    fn revert_error(err: ProviderError) -> anyhow::Error {
+        match RpcError::as_error_response(&err).and_then(JsonRpcError::as_revert_data) {
+            Some(data) => {
+                anyhow::Error::new(err).context(ClientError::Reverted(decode_revert(&data)))
+            }
+            None => err.into(),
+        }
+    }
+
+    pub async fn get_logs(&self, start_block: u64, end_block: u64) -> anyhow::Result<Vec<Log>> {
+        self.get_logs_spec(BlockSpec::Number(start_block), BlockSpec::Number(end_block))
+            .await
+    }
+
+    /// `get_logs` with tag or number bounds, e.g. `Earliest` to `Latest`.
+    /// Tags are resolved first so the range is chunked like a numeric one.
+    pub async fn get_logs_spec(&self, from: BlockSpec, to: BlockSpec) -> anyhow::Result<Vec<Log>> {
+        let from = self.resolve_block_spec(from).await?;
+        let to = self.resolve_block_spec(to).await?;
+        self.get_logs_filtered(Filter::new().from_block(from).to_block(to))
+            .await
+    }
+
+    /// Logs in `start_block..=end_block` emitted by any of `addresses` with
+    /// any of `topic0s` as their first topic, chunked like
+    /// [`EthereumClient::get_logs_filtered`]. An empty set leaves that part
+    /// of the filter unconstrained.
+    pub async fn get_logs_filtered_simple(
+        &self,
+        start_block: u64,
+        end_block: u64,
+        addresses: &[Address],
+        topic0s: &[H256],
+    ) -> anyhow::Result<Vec<Log>> {
+        let mut filter = Filter::new().from_block(start_block).to_block(end_block);
+        if !addresses.is_empty() {
+            filter = filter.address(addresses.to_vec());
+        }
+        if !topic0s.is_empty() {
+            filter = filter.topic0(topic0s.to_vec());
+        }
+        self.get_logs_filtered(filter).await
+    }
+
+    /// Number of logs matching `events` each of `addresses` emitted in
+    /// `from..=to`, from one address-array filter chunked like
+    /// [`EthereumClient::get_logs_filtered`] rather than a query per address.
+    /// Every address is in the map, with 0 if it emitted nothing; logs from
+    /// addresses outside the set are not counted.
+    pub async fn log_counts_by_address(
+        &self,
+        addresses: &[Address],
+        from: u64,
+        to: u64,
+        events: &[&str],
+    ) -> anyhow::Result<HashMap<Address, u64>> {
+        let mut counts: HashMap<Address, u64> =
+            addresses.iter().map(|address| (*address, 0)).collect();
+        if addresses.is_empty() {
+            return Ok(counts);
+        }
+
+        let mut filter = Filter::new()
+            .from_block(from)
+            .to_block(to)
+            .address(addresses.to_vec());
+        if !events.is_empty() {
+            filter = filter.events(events);
+        }
+        for log in self.get_logs_filtered(filter).await? {
+            if let Some(count) = counts.get_mut(&log.address) {
+                *count += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    async fn resolve_block_spec(&self, spec: BlockSpec) -> anyhow::Result<u64> {
+        match spec {
+            BlockSpec::Earliest => Ok(0),
+            BlockSpec::Latest => self.get_block_count().await,
+            BlockSpec::Finalized => {
+                let block = self.get_finalized_block().await?;
+                Ok(block.number.ok_or(ClientError::PendingBlock)?.as_u64())
+            }
+            BlockSpec::Number(number) => Ok(number),
+        }
+    }
+
+    /// Logs of exactly one block matching `events`, restricted to the client's
+    /// addresses when it has any. Pass a filter to `get_logs_filtered` instead
+    /// to use a different address set.
+    pub async fn get_logs_at_block(
+        &self,
+        block_hash: H256,
+        events: &[&str],
+    ) -> anyhow::Result<Vec<Log>> {
+        let mut filter = Filter::new().at_block_hash(block_hash);
+        if !self.addresses.is_empty() {
+            filter = filter.address(self.addresses.clone());
+        }
+        if !events.is_empty() {
+            filter = filter.events(events);
+        }
+        self.get_logs_filtered(filter).await
+    }
+
+    /// Runs an arbitrary filter. A numeric `from_block..=to_block` range is
+    /// split into `log_chunk_size` wide requests issued in order; tag bounds
+    /// and block hash filters go to the node as a single request. Retries are
+    /// whatever the transport provides, e.g. a `RetryClient` given to
+    /// [`EthereumClient::from_provider`].
+    pub async fn get_logs_filtered(&self, filter: Filter) -> anyhow::Result<Vec<Log>> {
+        let (Some(from), Some(to)) = (filter.get_from_block(), filter.get_to_block()) else {
+            return self.logs_or_fallback(&filter).await;
+        };
+
+        let mut logs = Vec::new();
+        for (start, end) in chunk_ranges(from.as_u64(), to.as_u64(), self.log_chunk_size) {
+            let chunk = filter.clone().from_block(start).to_block(end);
+            logs.extend(self.logs_or_fallback(&chunk).await?);
+        }
+        Ok(logs)
+    }
+
+    /// Writes the logs in `from..=to` matching `events`, restricted to the
+    /// client's addresses when it has any, to `writer` as `format` rows and
+    /// returns how many were written.
+    ///
+    /// The range is fetched in the same `log_chunk_size` windows as
+    /// [`EthereumClient::get_logs_filtered`], with its receipt scan fallback,
+    /// each window written out before the next is requested, so only one
+    /// window is held in memory. On error
+    /// the rows of earlier windows have already been written.
+    pub async fn export_logs<W: std::io::Write>(
+        &self,
+        from: u64,
+        to: u64,
+        events: &[&str],
+        format: ExportFormat,
+        writer: &mut W,
+    ) -> anyhow::Result<u64> {
+        let mut filter = Filter::new();
+        if !self.addresses.is_empty() {
+            filter = filter.address(self.addresses.clone());
+        }
+        if !events.is_empty() {
+            filter = filter.events(events);
+        }
+
+        format.write_header(writer)?;
+        let mut count = 0;
+        for (start, end) in chunk_ranges(from, to, self.log_chunk_size) {
+            let chunk = filter.clone().from_block(start).to_block(end);
+            for log in self.logs_or_fallback(&chunk).await? {
+                format.write_log(writer, &log)?;
+                count += 1;
+            }
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// One `eth_getLogs` request, or a receipt scan when the node does not
+    /// support it and `receipt_log_fallback` is on.
+    async fn logs_or_fallback(&self, filter: &Filter) -> anyhow::Result<Vec<Log>> {
+        let err = match self
+            .observe("eth_getLogs", self.provider.get_logs(filter))
+            .await
+        {
+            Ok(logs) => return Ok(logs),
+            Err(err) => err,
+        };
+        let range = filter.get_from_block().zip(filter.get_to_block());
+        match range {
+            Some((from, to)) if self.receipt_log_fallback && Self::is_unsupported(&err) => {
+                let started = Instant::now();
+                let logs = self
+                    .scan_receipt_logs(filter, from.as_u64(), to.as_u64())
+                    .await;
+                self.metrics
+                    .record("receipt_log_fallback", started.elapsed(), None);
+                logs
+            }
+            _ => Err(self.chain_error(err)),
+        }
+    }
+
+    fn is_unsupported(err: &ProviderError) -> bool {
+        RpcError::as_error_response(err).is_some_and(|response| {
+            let message = response.message.to_lowercase();
+            response.code == -32601
+                || message.contains("not supported")
+                || message.contains("unsupported")
+        })
+    }
+
+    async fn scan_receipt_logs(
+        &self,
+        filter: &Filter,
+        from: u64,
+        to: u64,
+    ) -> anyhow::Result<Vec<Log>> {
+        let mut logs = Vec::new();
+        for number in from..=to {
+            let block = self
+                .get_block(number)
+                .await?
+                .ok_or(ClientError::BlockNotFound(number))?;
+            for receipt in self
+                .get_transaction_receipts(&block.transactions)
+                .await?
+                .into_iter()
+                .flatten()
+            {
+                logs.extend(
+                    receipt
+                        .logs
+                        .into_iter()
+                        .filter(|log| Self::log_matches(filter, log)),
+                );
+            }
+        }
+        Ok(logs)
+    }
+
+    /// Address and topic matching as `eth_getLogs` does it.
+    fn log_matches(filter: &Filter, log: &Log) -> bool {
+        let address = match &filter.address {
+            Some(ValueOrArray::Value(address)) => *address == log.address,
+            Some(ValueOrArray::Array(addresses)) => {
+                addresses.is_empty() || addresses.contains(&log.address)
+            }
+            None => true,
+        };
+        address
+            && filter.topics.iter().enumerate().all(|(i, topic)| {
+                let actual = log.topics.get(i);
+                match topic {
+                    None | Some(ValueOrArray::Value(None)) => true,
+                    Some(ValueOrArray::Value(Some(expected))) => actual == Some(expected),
+                    Some(ValueOrArray::Array(options)) => {
+                        options.is_empty()
+                            || options
+                                .iter()
+                                .any(|option| option.is_none() || option.as_ref() == actual)
+                    }
+                }
+            })
+    }
+
+    fn leaf_encoder(&self) -> DefaultLeafEncoder {
+        DefaultLeafEncoder {
+            options: self.leaf_options,
+        }
+    }
+
+    /// Receipts of `block` in transaction order, `None` where the node has
+    /// none and `missing_receipts` tolerates that, together with the block
+    /// they belong to.
+    ///
+    /// Receipts are fetched concurrently. A missing receipt in a mined block
+    /// usually means the block was reorged out while fetching: the block is
+    /// then re-read by number and, if it was replaced, the receipts of the new
+    /// block are fetched instead, up to [`MAX_REORG_RETRIES`] times. The
+    /// returned block is then the replacement, and callers that read the
+    /// header or the transaction list have to use it rather than `block`.
+    /// Only a receipt missing from an unchanged block goes to
+    /// `missing_receipts`.
+    async fn block_receipts(
+        &self,
+        block: &Block<H256>,
+    ) -> anyhow::Result<(Block<H256>, Vec<(TxHash, Option<TransactionReceipt>)>)> {
+        let mut block = Cow::Borrowed(block);
+        let mut retries = 0;
+        loop {
+            let receipts = self.get_transaction_receipts(&block.transactions).await?;
+            let missing = block
+                .transactions
+                .iter()
+                .zip(&receipts)
+                .find_map(|(tx_hash, receipt)| receipt.is_none().then_some(*tx_hash));
+
+            if let (Some(_), Some(number)) = (missing, block.number) {
+                if retries < MAX_REORG_RETRIES {
+                    let current = self.get_block(number.as_u64()).await?;
+                    if let Some(current) = current.filter(|current| current.hash != block.hash) {
+                        block = Cow::Owned(current);
+                        retries += 1;
+                        continue;
+                    }
+                }
+            }
+            if let (Some(tx_hash), MissingReceipts::Error) = (missing, self.missing_receipts) {
+                return Err(ClientError::ReceiptNotFound(tx_hash).into());
+            }
+            let receipts = block.transactions.iter().copied().zip(receipts).collect();
+            return Ok((block.into_owned(), receipts));
+        }
+    }
+
+    /// Leaves of `block` in transaction order with their transaction index,
+    /// and the block they were built from, see `block_receipts`.
+    /// Transactions without a receipt are handled per `missing_receipts`.
+    async fn block_leaves(
+        &self,
+        block: &Block<H256>,
+        encoder: &impl LeafEncoder,
+    ) -> anyhow::Result<(Block<H256>, Vec<(u64, Vec<u8>)>)> {
+        let (block, receipts) = self.block_receipts(block).await?;
+        Ok((
+            block,
+            encode_leaves(&receipts, self.missing_receipts, encoder)?,
+        ))
+    }
+
+    /// Receipts are fetched on the runtime; encoding the leaves and hashing
+    /// the tree, CPU bound for large blocks, run on tokio's blocking pool.
+    pub async fn get_transaction_merkle(&self, block: &Block<H256>) -> anyhow::Result<MerkleTree> {
+        self.get_transaction_merkle_spawned(block, self.leaf_encoder())
+            .await
+    }
+
+    /// `get_transaction_merkle_with`, but leaf encoding and tree building run
+    /// in `tokio::task::spawn_blocking` so large blocks do not stall other
+    /// tasks. Needs to be called from within a tokio runtime.
+    pub async fn get_transaction_merkle_spawned<E>(
+        &self,
+        block: &Block<H256>,
+        encoder: E,
+    ) -> anyhow::Result<MerkleTree>
+    where
+        E: LeafEncoder + Send + 'static,
+    {
+        let (_, receipts) = self.block_receipts(block).await?;
+        let missing_receipts = self.missing_receipts;
+        tokio::task::spawn_blocking(move || {
+            let leaves = encode_leaves(&receipts, missing_receipts, &encoder)?;
+            let leaves: Vec<_> = leaves.into_iter().map(|(_, leaf)| leaf).collect();
+            Ok(MerkleTree::build(&leaves))
+        })
+        .await?
+    }
+
+    /// `get_transaction_merkle` with leaves produced by `encoder`.
+    pub async fn get_transaction_merkle_with(
+        &self,
+        block: &Block<H256>,
+        encoder: &impl LeafEncoder,
+    ) -> anyhow::Result<MerkleTree> {
+        let txs: Vec<_> = self
+            .block_leaves(block, encoder)
+            .await?
+            .1
+            .into_iter()
+            .map(|(_, leaf)| leaf)
+            .collect();
+        Ok(MerkleTree::build(&txs))
+    }
+
+    /// `get_transaction_merkle` over only the receipts `filter` accepts,
+    /// e.g. `|r| r.status == Some(1.into())` to leave out reverted
+    /// transactions. Included leaves keep their block order. The root depends
+    /// on the filter, so verifiers have to rebuild it with the same
+    /// predicate. Missing receipts never reach `filter` and follow
+    /// `missing_receipts` as usual.
+    pub async fn get_transaction_merkle_where(
+        &self,
+        block: &Block<H256>,
+        filter: impl Fn(&TransactionReceipt) -> bool,
+    ) -> anyhow::Result<MerkleTree> {
+        let (_, mut receipts) = self.block_receipts(block).await?;
+        receipts.retain(|(_, receipt)| receipt.as_ref().is_none_or(&filter));
+        let leaves: Vec<_> = encode_leaves(&receipts, self.missing_receipts, &self.leaf_encoder())?
+            .into_iter()
+            .map(|(_, leaf)| leaf)
+            .collect();
+        Ok(MerkleTree::build(&leaves))
+    }
+
+    pub async fn get_root_merkle(
+        &self,
+        block: &Block<H256>,
+        index: Option<u64>,
+    ) -> anyhow::Result<(MerkleTreeRoot, MerkleTreeProof, Vec<u8>)> {
+        self.get_root_merkle_with(block, index, &self.leaf_encoder())
+            .await
+    }
+
+    /// `get_root_merkle` with leaves produced by `encoder`.
+    ///
+    /// `index` is a position in `block.transactions`. If the block was
+    /// replaced by a reorg while fetching receipts, the transaction at that
+    /// index is looked up in the replacement, failing with
+    /// [`ClientError::TransactionNotInBlock`] when it is no longer there.
+    pub async fn get_root_merkle_with(
+        &self,
+        block: &Block<H256>,
+        index: Option<u64>,
+        encoder: &impl LeafEncoder,
+    ) -> anyhow::Result<(MerkleTreeRoot, MerkleTreeProof, Vec<u8>)> {
+        let (current, leaves) = self.block_leaves(block, encoder).await?;
+        let index = match index {
+            Some(index) if current.hash != block.hash => {
+                let tx_hash = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| block.transactions.get(index).copied());
+                match tx_hash {
+                    Some(tx_hash) => Some(
+                        current
+                            .transactions
+                            .iter()
+                            .position(|hash| *hash == tx_hash)
+                            .ok_or(ClientError::TransactionNotInBlock {
+                                tx_hash,
+                                block_number: current.number.unwrap_or_default().as_u64(),
+                            })? as u64,
+                    ),
+                    None => Some(index),
+                }
+            }
+            index => index,
+        };
+        let i = index
+            .and_then(|c| leaves.iter().position(|(tx_index, _)| *tx_index == c))
+            .unwrap_or(0);
+        let items: Vec<_> = leaves.into_iter().map(|(_, leaf)| leaf).collect();
+        let merkle = MerkleTree::build(&items);
+
+        Ok((merkle.root, merkle.proofs[i].clone(), items[i].clone()))
+    }
+
+    /// Proof that `tx_hash` is one of the leaves of `block_number`'s
+    /// transaction tree. The index and proof come from the block the tree was
+    /// actually built from, so a reorg while fetching receipts either proves
+    /// `tx_hash` in the replacement block or fails with
+    /// [`ClientError::TransactionNotInBlock`].
+    pub async fn prove_transaction(
+        &self,
+        block_number: u64,
+        tx_hash: TxHash,
+    ) -> anyhow::Result<TransactionInclusionProof> {
+        let block = self
+            .get_block(block_number)
+            .await?
+            .ok_or(ClientError::BlockNotFound(block_number))?;
+        let not_in_block = ClientError::TransactionNotInBlock {
+            tx_hash,
+            block_number,
+        };
+        if !block.transactions.contains(&tx_hash) {
+            return Err(not_in_block.into());
+        }
+
+        let (block, leaves) = self.block_leaves(&block, &self.leaf_encoder()).await?;
+        let transaction_index = block
+            .transactions
+            .iter()
+            .position(|hash| *hash == tx_hash)
+            .ok_or(not_in_block)? as u64;
+        let position = leaves
+            .iter()
+            .position(|(index, _)| *index == transaction_index)
+            .ok_or(ClientError::ReceiptNotFound(tx_hash))?;
+        let mut items: Vec<_> = leaves.into_iter().map(|(_, leaf)| leaf).collect();
+        let merkle = MerkleTree::build(&items);
+
+        Ok(TransactionInclusionProof {
+            block_number,
+            transaction_index,
+            root: merkle.root,
+            proof: merkle.proofs[position].clone(),
+            leaf: items.swap_remove(position),
+        })
+    }
+
+    /// Rebuilds the transaction tree of `block_number` and compares its root
+    /// with `expected`, returning the local root alongside the verdict.
+    pub async fn verify_block_root(
+        &self,
+        block_number: u64,
+        expected: MerkleTreeRoot,
+    ) -> anyhow::Result<(bool, MerkleTreeRoot)> {
+        let block = self
+            .get_block(block_number)
+            .await?
+            .ok_or(ClientError::BlockNotFound(block_number))?;
+        let root = self.get_transaction_merkle(&block).await?.root;
+        Ok((root == expected, root))
+    }
+
+    /// Merkle-Patricia proof of transaction `index` under the canonical
+    /// `transactionsRoot` of `block_number`, checkable against the header
+    /// alone. The trie is rebuilt from the block's transactions and rejected
+    /// with [`ClientError::TransactionsRootMismatch`] if it does not reproduce
+    /// the header root, e.g. for transaction types ethers cannot encode.
+    pub async fn mpt_transaction_proof(
+        &self,
+        block_number: u64,
+        index: u64,
+    ) -> anyhow::Result<TrieProof> {
+        let block = self
+            .get_block_full(block_number)
+            .await?
+            .ok_or(ClientError::BlockNotFound(block_number))?;
+
+        let encoded: Vec<Vec<u8>> = block
+            .transactions
+            .iter()
+            .map(|tx| tx.rlp().to_vec())
+            .collect();
+        let trie = Trie::from_ordered(&encoded);
+        let computed = trie.root();
+        if computed != block.transactions_root {
+            return Err(ClientError::TransactionsRootMismatch {
+                block_number,
+                expected: block.transactions_root,
+                computed,
+            }
+            .into());
+        }
+
+        trie.prove_index(index).ok_or_else(|| {
+            ClientError::TransactionIndexOutOfRange {
+                block_number,
+                index,
+            }
+            .into()
+        })
+    }
+
+    /// Leaf count and receipt tree root of block `block_number`. The root is
+    /// the one `get_transaction_merkle` builds, computed incrementally
+    /// without materializing proofs.
+    pub async fn block_anchor(&self, block_number: u64) -> anyhow::Result<BlockAnchor> {
+        let block = self
+            .get_block(block_number)
+            .await?
+            .ok_or(ClientError::BlockNotFound(block_number))?;
+        let (_, leaves) = self.block_leaves(&block, &self.leaf_encoder()).await?;
+        let mut tree = IncrementalMerkleTree::default();
+        for (_, leaf) in &leaves {
+            tree.push(leaf);
+        }
+        Ok(BlockAnchor {
+            number: block_number,
+            leaf_count: leaves.len(),
+            root: tree.root(),
+        })
+    }
+
+    /// [`block_anchor`](Self::block_anchor) roots of several blocks, a block
+    /// per concurrency slot at a time, in the order of `block_numbers`. The
+    /// requests each block makes still go through the client's limiter. A
+    /// block that fails does not stop the others; its error is paired with
+    /// its number.
+    pub async fn block_roots(
+        &self,
+        block_numbers: &[u64],
+    ) -> Vec<(u64, anyhow::Result<MerkleTreeRoot>)> {
+        futures::stream::iter(block_numbers)
+            .map(|&number| async move {
+                let root = self.block_anchor(number).await.map(|anchor| anchor.root);
+                (number, root)
+            })
+            .buffered(self.limiter.max())
+            .collect()
+            .await
+    }
+
+    /// Both roots of `block` packed for submission to an anchoring contract.
+    /// Should a reorg replace `block` while its receipts are fetched, both
+    /// roots and the number describe the replacement.
+    pub async fn anchor_params(&self, block: &Block<H256>) -> anyhow::Result<RootParam> {
+        let (block, leaves) = self.block_leaves(block, &self.leaf_encoder()).await?;
+        let leaves: Vec<_> = leaves.into_iter().map(|(_, leaf)| leaf).collect();
+        let root = MerkleTree::build(&leaves).root;
+        let (tx_root, _) = EthereumClient::get_hash_merkle(&block, None);
+        RootParam::new(&block, root, tx_root)
+    }
+}
+
+// Provider independent helpers live on the default client so that
+// `EthereumClient::helper(..)` resolves without a transport annotation.
+impl EthereumClient {
+    /// Fee of `receipt` at `gas_price`, see [`EthereumClient::transaction_fee`].
+    pub fn receipt_fee(
+        chain_id: u64,
+        receipt: &TransactionReceipt,
+        gas_price: U256,
+    ) -> anyhow::Result<U256> {
+        let gas_used = receipt
+            .gas_used
+            .ok_or_else(|| anyhow::anyhow!("receipt without gas used"))?;
+        let l1_fee = match receipt.other.get_deserialized::<U256>("l1Fee") {
+            Some(fee) if L1_FEE_CHAINS.contains(&chain_id) => fee?,
+            _ => U256::zero(),
+        };
+        gas_used
+            .checked_mul(gas_price)
+            .and_then(|fee| fee.checked_add(l1_fee))
+            .ok_or_else(|| anyhow::anyhow!("transaction fee overflows"))
+    }
+
+    /// Sender recovered from the transaction's `(r, s, v)` over its signing
+    /// hash, which `ethers` derives per transaction type (legacy with or
+    /// without EIP-155, EIP-2930, EIP-1559). For transactions from untrusted
+    /// sources: a `from` that is set but differs from the signer fails with
+    /// [`ClientError::SenderMismatch`].
+    pub fn recover_sender(tx: &EtherTransaction) -> anyhow::Result<Address> {
+        let recovered = tx.recover_from()?;
+        if !tx.from.is_zero() && tx.from != recovered {
+            return Err(ClientError::SenderMismatch {
+                claimed: tx.from,
+                recovered,
+            }
+            .into());
+        }
+        Ok(recovered)
+    }
+
+    /// ERC-20 `Transfer` filter over every token in `tokens`, ready for a sync.
+    pub fn erc20_transfer_filter(tokens: &[Address]) -> Filter {
+        Filter::new()
+            .address(tokens.to_vec())
+            .event("Transfer(address,address,uint256)")
+    }
+
+    /// Tags each log with the signature in `events` its topic0 matches, the
+    /// post-processing counterpart of `new_sync(from, events)`.
+    pub fn tag_logs(events: &[&str], logs: Vec<Log>) -> TaggedLogs {
+        let topics: Vec<(H256, &str)> = events
+            .iter()
+            .map(|event| (event_topic0(event), *event))
+            .collect();
+
+        let mut tagged = TaggedLogs::default();
+        for log in logs {
+            let event = log.topics.first().and_then(|topic0| {
+                topics
+                    .iter()
+                    .find(|(topic, _)| topic == topic0)
+                    .map(|(_, event)| event.to_string())
+            });
+            match event {
+                Some(event) => tagged.matched.push((event, log)),
+                None => tagged.unmatched.push(log),
+            }
+        }
+        tagged
+    }
+
+    /// Numbers in `from..=to` that are not in `seen`, ascending. `seen` may
+    /// be unordered, repeat numbers and reach outside the range.
+    pub fn find_missing_blocks(seen: &[u64], from: u64, to: u64) -> Vec<u64> {
+        let seen: HashSet<u64> = seen.iter().copied().collect();
+        (from..=to)
+            .filter(|number| !seen.contains(number))
+            .collect()
+    }
+
+    /// Groups `logs`, e.g. a `fetch_event` batch, by transaction.
+    pub fn group_logs_by_transaction(logs: Vec<Log>) -> LogsByTransaction {
+        let mut grouped = LogsByTransaction::default();
+        for log in logs {
+            match log.transaction_hash {
+                Some(tx_hash) => grouped.groups.entry(tx_hash).or_default().push(log),
+                None => grouped.without_hash.push(log),
+            }
+        }
+        for group in grouped.groups.values_mut() {
+            group.sort_by_key(|log| log.log_index);
+        }
+        grouped
+    }
+
+    fn data_slice<T>(datas: &[T]) -> Vec<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        datas
+            .iter()
+            .map(|f| string_to_crypto_hash(&serde_json::to_string(f).unwrap()).to_vec())
+            .collect()
+    }
+
+    /// Cheap commitment over the block's transaction hashes: each 32 byte
+    /// hash is a leaf as is, with no receipts fetched, no JSON and no extra
+    /// `transactions_root` leaf. Unlike [`get_hash_merkle`](Self::get_hash_merkle)
+    /// the proofs verify with [`MerkleTreeRoot::verify_prehashed`] against the
+    /// bare transaction hash.
+    pub fn hashes_merkle(block: &Block<H256>) -> MerkleTree {
+        let leaves: Vec<_> = block.transactions.iter().map(|hash| hash.0).collect();
+        MerkleTree::build_prehashed(&leaves, HashAlgo::Keccak256)
+    }
+
+    /// Tree over the block's transaction hashes followed by
+    /// `block.transactions_root` as one extra, last leaf.
+    ///
+    /// The appended header field ties the tree to the block itself: proving
+    /// that leaf shows the root was built for a block with exactly that
+    /// transactions trie, so hashes cannot be replayed under another block.
+    /// Without `transaction_hash` the proof is for the first transaction; use
+    /// [`get_transactions_root_proof`](Self::get_transactions_root_proof) for
+    /// the root leaf.
+    pub fn get_hash_merkle(
+        block: &Block<H256>,
+        transaction_hash: Option<H256>,
+    ) -> (MerkleTreeRoot, MerkleTreeProof) {
+        let index = transaction_hash.map(|hx| {
+            block
+                .transactions
+                .iter()
+                .chain([&block.transactions_root])
+                .position(|h| h == &hx)
+                .expect("Transaction hash not found")
+        });
+        Self::hash_merkle_at(block, index.unwrap_or(0))
+    }
+
+    /// Proof for the `transactions_root` leaf appended by
+    /// [`get_hash_merkle`](Self::get_hash_merkle).
+    pub fn get_transactions_root_proof(block: &Block<H256>) -> (MerkleTreeRoot, MerkleTreeProof) {
+        Self::hash_merkle_at(block, block.transactions.len())
+    }
+
+    fn hash_merkle_at(block: &Block<H256>, index: usize) -> (MerkleTreeRoot, MerkleTreeProof) {
+        let mut tx_hashs = block.transactions.clone();
+        tx_hashs.push(block.transactions_root);
+
+        let hash_items = Self::data_slice(&tx_hashs);
+        let merkle = MerkleTree::build(&hash_items);
+        (merkle.root, merkle.proofs[index].clone())
+    }
+}
+
+fn encode_leaves(
+    receipts: &[(TxHash, Option<TransactionReceipt>)],
+    missing_receipts: MissingReceipts,
+    encoder: &impl LeafEncoder,
+) -> anyhow::Result<Vec<(u64, Vec<u8>)>> {
+    let mut leaves = Vec::with_capacity(receipts.len());
+    for (position, (tx_hash, receipt)) in receipts.iter().enumerate() {
+        match receipt {
+            Some(receipt) => {
+                leaves.push((receipt.transaction_index.as_u64(), encoder.encode(receipt)?))
+            }
+            None => match missing_receipts {
+                MissingReceipts::Error => return Err(ClientError::ReceiptNotFound(*tx_hash).into()),
+                MissingReceipts::Placeholder => {
+                    leaves.push((position as u64, Transaction::placeholder_leaf(tx_hash)))
+                }
+                MissingReceipts::Skip => {}
+            },
+        }
+    }
+    Ok(leaves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mocked_client() -> (EthereumClient<MockProvider>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        let client = EthereumClient::from_provider(provider, "test", 1, 0, vec![]);
+        (client, mock)
+    }
+
+    #[tokio::test]
+    async fn from_provider_uses_the_given_provider() {
+        let (client, mock) = mocked_client();
+        mock.push(U64::from(42)).unwrap();
+
+        assert_eq!(client.get_block_count().await.unwrap(), 42);
+        mock.assert_request("eth_blockNumber", ()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_transaction_full_pairs_body_and_receipt() {
+        let (client, mock) = mocked_client();
+        let hash = H256::repeat_byte(7);
+        // the mock answers newest-first: body request, then receipt request
+        mock.push(TransactionReceipt {
+            transaction_hash: hash,
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push(EtherTransaction {
+            hash,
+            block_number: Some(U64::from(5)),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let (tx, receipt) = client.get_transaction_full(hash).await.unwrap().unwrap();
+        assert_eq!(tx.hash, hash);
+        assert_eq!(receipt.transaction_hash, hash);
+
+        // pending: known body, no receipt yet
+        mock.push(serde_json::Value::Null).unwrap();
+        mock.push(EtherTransaction {
+            hash,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(client.get_transaction_full(hash).await.unwrap().is_none());
+    }
+
+    fn block_at(number: u64, timestamp: u64) -> Block<TxHash> {
+        Block {
+            number: Some(number.into()),
+            timestamp: timestamp.into(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_tagged_block_is_an_unsupported_tag() {
+        let (client, mock) = mocked_client();
+        mock.push::<Option<Block<TxHash>>, _>(None).unwrap();
+
+        let err = client.get_finalized_block().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::UnsupportedBlockTag(BlockNumber::Finalized))
+        ));
+        mock.assert_request("eth_getBlockByNumber", ("finalized", false))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejected_block_tag_is_an_unsupported_tag() {
+        let (client, mock) = mocked_client();
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32602,
+            message: "invalid block number".into(),
+            data: None,
+        }));
+
+        let err = client.get_safe_block().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::UnsupportedBlockTag(BlockNumber::Safe))
+        ));
+    }
+
+    #[tokio::test]
+    async fn block_at_timestamp_finds_first_block_at_or_after_target() {
+        let (client, mock) = mocked_client();
+        // head 7, then probes 3, 5, 4; answered newest-first
+        mock.push(block_at(4, 140)).unwrap();
+        mock.push(block_at(5, 150)).unwrap();
+        mock.push(block_at(3, 130)).unwrap();
+        mock.push(block_at(7, 170)).unwrap();
+        mock.push(U64::from(7)).unwrap();
+
+        assert_eq!(client.block_at_timestamp(135).await.unwrap(), 4);
+
+        mock.push(block_at(7, 170)).unwrap();
+        mock.push(U64::from(7)).unwrap();
+        let err = client.block_at_timestamp(500).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::TimestampAfterHead { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn log_counts_by_address_tallies_one_query() {
+        let (client, mock) = mocked_client();
+        let (busy, quiet, other) = (
+            Address::repeat_byte(1),
+            Address::repeat_byte(2),
+            Address::repeat_byte(3),
+        );
+        let log = |address| Log {
+            address,
+            ..Default::default()
+        };
+        mock.push::<Vec<Log>, _>(vec![log(busy), log(other), log(busy)])
+            .unwrap();
+
+        let counts = client
+            .log_counts_by_address(&[busy, quiet], 0, 9, &[])
+            .await
+            .unwrap();
+        assert_eq!(counts, HashMap::from([(busy, 2), (quiet, 0)]));
+
+        let filter = Filter::new()
+            .from_block(0)
+            .to_block(9)
+            .address(vec![busy, quiet]);
+        mock.assert_request("eth_getLogs", [filter]).unwrap();
+    }
+
+    #[tokio::test]
+    async fn export_logs_streams_each_window_as_rows() {
+        let (client, mock) = mocked_client();
+        let client = client.with_log_chunk_size(10);
+        let log = |block: u64| Log {
+            address: Address::repeat_byte(1),
+            topics: vec![H256::repeat_byte(2)],
+            data: vec![0xab].into(),
+            block_number: Some(block.into()),
+            transaction_hash: Some(H256::repeat_byte(3)),
+            log_index: Some(0.into()),
+            ..Default::default()
+        };
+        mock.push::<Vec<Log>, _>(vec![log(12)]).unwrap();
+        mock.push::<Vec<Log>, _>(vec![log(4)]).unwrap();
+
+        let mut out = Vec::new();
+        let count = client
+            .export_logs(0, 14, &[], ExportFormat::Csv, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            "block_number,transaction_hash,log_index,address,topics,data"
+        );
+        assert_eq!(
+            lines[1],
+            format!(
+                "4,{:?},0,{:?},{:?},0xab",
+                H256::repeat_byte(3),
+                Address::repeat_byte(1),
+                H256::repeat_byte(2)
+            )
+        );
+        assert!(lines[2].starts_with("12,"));
+        assert_eq!(lines.len(), 3);
+
+        mock.push::<Vec<Log>, _>(vec![log(4)]).unwrap();
+        let mut out = Vec::new();
+        client
+            .export_logs(0, 5, &[], ExportFormat::Jsonl, &mut out)
+            .await
+            .unwrap();
+        let row: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(row["block_number"], 4);
+        assert_eq!(row["topics"][0], format!("{:?}", H256::repeat_byte(2)));
+        assert_eq!(row["data"], "0xab");
+    }
+
+    #[tokio::test]
+    async fn get_logs_filtered_chunks_numeric_ranges_in_order() {
+        let (client, mock) = mocked_client();
+        let client = client.with_log_chunk_size(10);
+        for index in [2u64, 1, 0] {
+            mock.push::<Vec<Log>, _>(vec![Log {
+                log_index: Some(index.into()),
+                ..Default::default()
+            }])
+            .unwrap();
+        }
+
+        let filter = Filter::new().address(Address::repeat_byte(1));
+        let logs = client
+            .get_logs_filtered(filter.clone().from_block(0).to_block(25))
+            .await
+            .unwrap();
+        let indices: Vec<_> = logs.iter().map(|l| l.log_index.unwrap().as_u64()).collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+
+        for (from, to) in [(0u64, 9u64), (10, 19), (20, 25)] {
+            let chunk = filter.clone().from_block(from).to_block(to);
+            mock.assert_request("eth_getLogs", [chunk]).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn a_zero_log_chunk_size_set_on_the_field_queries_single_blocks() {
+        let (mut client, mock) = mocked_client();
+        client.log_chunk_size = 0;
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+
+        let filter = Filter::new().from_block(3).to_block(4);
+        assert!(client
+            .get_logs_filtered(filter.clone())
+            .await
+            .unwrap()
+            .is_empty());
+        for block in [3u64, 4] {
+            let chunk = filter.clone().from_block(block).to_block(block);
+            mock.assert_request("eth_getLogs", [chunk]).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_event_advances_by_whole_windows_behind_the_head() {
+        let (client, mock) = mocked_client();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        let mut sync = SyncData {
+            cur: 120,
+            n: 50,
+            ..SyncData::empty(10)
+        };
+
+        let (_, next) = client.fetch_event(&mut sync).await.unwrap();
+
+        assert_eq!((sync.from, next), (60, 60));
+        let window = Filter::new().from_block(10).to_block(59);
+        mock.assert_request("eth_getLogs", [window]).unwrap();
+    }
+
+    #[tokio::test]
+    async fn new_sync_for_overrides_addresses_without_touching_the_client() {
+        let (provider, mock) = Provider::mocked();
+        let own = vec![Address::repeat_byte(1), Address::repeat_byte(2)];
+        let client = EthereumClient::from_provider(provider, "test", 1, 0, own.clone());
+        mock.push(U64::from(100)).unwrap();
+        mock.push(U64::from(100)).unwrap();
+
+        let subset = [own[1]];
+        let sync = client.new_sync_for(5, &[], Some(&subset)).await.unwrap();
+        assert_eq!(
+            sync.filters.address,
+            Some(ValueOrArray::Array(subset.to_vec()))
+        );
+        let sync = client.new_sync(5, &[]).await.unwrap();
+        assert_eq!(sync.filters.address, Some(ValueOrArray::Array(own.clone())));
+        assert_eq!(client.addresses, own);
+    }
+
+    #[tokio::test]
+    async fn fetch_event_covers_every_block_once_across_the_tip_transition() {
+        let (client, mock) = mocked_client();
+        let client = client.with_poll_interval(Duration::ZERO);
+        let windows = [(10u64, 59u64), (60, 109), (110, 120), (121, 122)];
+        let log_at = |block: u64| Log {
+            block_number: Some(block.into()),
+            ..Default::default()
+        };
+        // responses in request order, pushed newest first
+        let mut responses = vec![];
+        for (i, (from, to)) in windows.iter().enumerate() {
+            if i >= 2 {
+                responses.push(serde_json::to_value(U64::from(125)).unwrap());
+            }
+            let logs: Vec<_> = (*from..=*to).map(log_at).collect();
+            responses.push(serde_json::to_value(logs).unwrap());
+        }
+        responses.push(serde_json::to_value(U64::from(125)).unwrap());
+        for response in responses.iter().rev() {
+            mock.push(response.clone()).unwrap();
+        }
+
+        let mut sync = SyncData {
+            cur: 120,
+            n: 50,
+            ..SyncData::empty(10)
+        };
+        let mut blocks = vec![];
+        for _ in 0..5 {
+            let (logs, _) = client.fetch_event(&mut sync).await.unwrap();
+            blocks.extend(logs.added.iter().map(|l| l.block_number.unwrap().as_u64()));
+        }
+
+        assert_eq!(blocks, (10..=122).collect::<Vec<_>>());
+        assert_eq!((sync.from, sync.cur), (123, 122));
+        for (i, (from, to)) in windows.into_iter().enumerate() {
+            if i >= 2 {
+                mock.assert_request("eth_blockNumber", ()).unwrap();
+            }
+            let window = Filter::new().from_block(from).to_block(to);
+            mock.assert_request("eth_getLogs", [window]).unwrap();
+        }
+        // caught up: only the head is polled
+        mock.assert_request("eth_blockNumber", ()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn oversized_windows_are_halved_until_they_fit() {
+        let (client, mock) = mocked_client();
+        let client = client.with_max_logs_per_window(2);
+        let logs = |count: usize| vec![Log::default(); count];
+        mock.push::<Vec<Log>, _>(logs(1)).unwrap();
+        mock.push::<Vec<Log>, _>(logs(3)).unwrap();
+        mock.push::<Vec<Log>, _>(logs(5)).unwrap();
+        let mut sync = SyncData {
+            cur: 120,
+            n: 50,
+            ..SyncData::empty(10)
+        };
+
+        let (fetched, next) = client.fetch_event(&mut sync).await.unwrap();
+
+        assert_eq!(fetched.added.len(), 1);
+        assert_eq!((sync.from, next), (23, 23));
+        for (from, to) in [(10, 59), (10, 34), (10, 22)] {
+            let window = Filter::new().from_block(from).to_block(to);
+            mock.assert_request("eth_getLogs", [window]).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn configured_events_are_hashed_once_and_used_by_syncs() {
+        let (client, mock) = mocked_client();
+        let transfer = "Transfer(address,address,uint256)";
+        let client = client.with_events(&[transfer]);
+        assert_eq!(client.event_topics(), &[event_topic0(transfer)]);
+        mock.push(U64::from(100)).unwrap();
+        mock.push(U64::from(100)).unwrap();
+
+        let configured = client.new_sync(0, &[]).await.unwrap();
+        let explicit = client.new_sync(0, &[transfer]).await.unwrap();
+        assert_eq!(configured.filters.topics[0], explicit.filters.topics[0]);
+    }
+
+    #[tokio::test]
+    async fn sync_tracked_records_only_windows_the_consumer_moved_past() {
+        let (client, mock) = mocked_client();
+        let client = client.with_poll_interval(Duration::ZERO);
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push(U64::from(126)).unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push(U64::from(125)).unwrap();
+        mock.push(U64::from(123)).unwrap();
+
+        let (stream, checkpoint) = client.sync_tracked(10, &[]);
+        let mut stream = Box::pin(stream);
+        stream.next().await.unwrap().unwrap();
+        assert_eq!(checkpoint.last_processed_block(), None);
+        stream.next().await.unwrap().unwrap();
+        assert_eq!(checkpoint.last_processed_block(), Some(120));
+
+        // dropped while the second window (121..=122) is being handled
+        drop(stream);
+        assert_eq!(checkpoint.last_processed_block(), Some(120));
+    }
+
+    #[tokio::test]
+    async fn sync_streams_windows_and_retries_failed_ones() {
+        let (client, mock) = mocked_client();
+        let client = client.with_poll_interval(Duration::ZERO);
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push(U64::from(126)).unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push(U64::from(125)).unwrap();
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32000,
+            message: "query timeout".into(),
+            data: None,
+        }));
+        mock.push(U64::from(124)).unwrap();
+        mock.push(U64::from(123)).unwrap();
+
+        let mut sync = std::pin::pin!(client.sync(10, &[]));
+        assert!(sync.next().await.unwrap().is_err());
+        let (_, checkpoint) = sync.next().await.unwrap().unwrap();
+        assert_eq!(checkpoint, 121);
+        let (_, checkpoint) = sync.next().await.unwrap().unwrap();
+        assert_eq!(checkpoint, 123);
+
+        let window = |from: u64, to: u64| {
+            Filter::new()
+                .address(Vec::<Address>::new())
+                .events(Vec::<&str>::new())
+                .from_block(from)
+                .to_block(to)
+        };
+        mock.assert_request("eth_blockNumber", ()).unwrap();
+        mock.assert_request("eth_blockNumber", ()).unwrap();
+        mock.assert_request("eth_getLogs", [window(10, 120)])
+            .unwrap();
+        mock.assert_request("eth_blockNumber", ()).unwrap();
+        mock.assert_request("eth_getLogs", [window(10, 120)])
+            .unwrap();
+        mock.assert_request("eth_blockNumber", ()).unwrap();
+        mock.assert_request("eth_getLogs", [window(121, 122)])
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_logs_filtered_simple_leaves_empty_sets_unconstrained() {
+        let (client, mock) = mocked_client();
+        let token = Address::repeat_byte(9);
+        let topic = event_topic0("Transfer(address,address,uint256)");
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+
+        client
+            .get_logs_filtered_simple(1, 2, &[token], &[])
+            .await
+            .unwrap();
+        client
+            .get_logs_filtered_simple(1, 2, &[], &[topic])
+            .await
+            .unwrap();
+
+        let range = Filter::new().from_block(1).to_block(2);
+        mock.assert_request("eth_getLogs", [range.clone().address(vec![token])])
+            .unwrap();
+        mock.assert_request("eth_getLogs", [range.topic0(vec![topic])])
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_logs_spec_resolves_tags_before_querying() {
+        let (client, mock) = mocked_client();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push(U64::from(30)).unwrap();
+
+        client
+            .get_logs_spec(BlockSpec::Number(12), BlockSpec::Latest)
+            .await
+            .unwrap();
+
+        mock.assert_request("eth_blockNumber", ()).unwrap();
+        let range = Filter::new().from_block(12).to_block(30);
+        mock.assert_request("eth_getLogs", [range]).unwrap();
+    }
+
+    #[tokio::test]
+    async fn fetch_event_desc_walks_windows_newest_first() {
+        let (client, mock) = mocked_client();
+        for _ in 0..3 {
+            mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        }
+        let filter = Filter::new().address(Address::repeat_byte(1));
+        let mut sync = SyncData {
+            cur: 120,
+            n: 50,
+            filters: filter.clone(),
+            ..SyncData::empty(0)
+        };
+
+        while client.fetch_event_desc(&mut sync).await.unwrap().is_some() {}
+
+        for (from, to) in [(71u64, 120u64), (21, 70), (0, 20)] {
+            let window = filter.clone().from_block(from).to_block(to);
+            mock.assert_request("eth_getLogs", [window]).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_event_desc_falls_back_to_receipts() {
+        let (client, mock) = mocked_client();
+        let client = client.with_receipt_log_fallback(true);
+        let token = Address::repeat_byte(1);
+        let tx_hash = H256::repeat_byte(7);
+        let matching = Log {
+            address: token,
+            topics: vec![event_topic0("Transfer(address,address,uint256)")],
+            ..Default::default()
+        };
+
+        mock.push(TransactionReceipt {
+            transaction_hash: tx_hash,
+            logs: vec![matching.clone()],
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push(Block::<H256> {
+            number: Some(5.into()),
+            transactions: vec![tx_hash],
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32601,
+            message: "the method eth_getLogs does not exist/is not available".into(),
+            data: None,
+        }));
+        let mut sync = SyncData {
+            cur: 5,
+            n: 1,
+            filters: EthereumClient::erc20_transfer_filter(&[token]),
+            ..SyncData::empty(5)
+        };
+
+        let fetched = client.fetch_event_desc(&mut sync).await.unwrap().unwrap();
+
+        assert_eq!(fetched.added, vec![matching]);
+        assert_eq!(
+            client.metrics_snapshot().methods["receipt_log_fallback"].calls,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn unsupported_get_logs_falls_back_to_receipts() {
+        let (client, mock) = mocked_client();
+        let client = client.with_receipt_log_fallback(true);
+        let token = Address::repeat_byte(1);
+        let tx_hash = H256::repeat_byte(7);
+        let matching = Log {
+            address: token,
+            topics: vec![event_topic0("Transfer(address,address,uint256)")],
+            ..Default::default()
+        };
+        let other = Log {
+            address: Address::repeat_byte(2),
+            ..matching.clone()
+        };
+
+        mock.push(TransactionReceipt {
+            transaction_hash: tx_hash,
+            logs: vec![other, matching.clone()],
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push(Block::<H256> {
+            number: Some(5.into()),
+            transactions: vec![tx_hash],
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32601,
+            message: "the method eth_getLogs does not exist/is not available".into(),
+            data: None,
+        }));
+
+        let filter = EthereumClient::erc20_transfer_filter(&[token])
+            .from_block(5)
+            .to_block(5);
+        let logs = client.get_logs_filtered(filter).await.unwrap();
+
+        assert_eq!(logs, vec![matching]);
+        assert_eq!(
+            client.metrics_snapshot().methods["receipt_log_fallback"].calls,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn get_block_transactions_orders_by_transaction_index() {
+        let (client, mock) = mocked_client();
+        let hashes: Vec<_> = (1..=3).map(H256::from_low_u64_be).collect();
+        // answers for hashes[0], hashes[1], hashes[2] carry indices 2, 0, 1
+        for (hash, index) in hashes.iter().zip([2u64, 0, 1]).rev() {
+            mock.push(EtherTransaction {
+                hash: *hash,
+                transaction_index: Some(index.into()),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        mock.push(Block::<TxHash> {
+            transactions: hashes,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let txs = client.get_block_transactions(1).await.unwrap();
+        let indices: Vec<_> = txs
+            .iter()
+            .map(|tx| tx.transaction_index.unwrap().as_u64())
+            .collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn fetch_receipts_batched_reports_progress_and_stops_on_missing() {
+        let (client, mock) = mocked_client();
+        let hashes: Vec<_> = (1..=5).map(H256::from_low_u64_be).collect();
+        // batches of two: [1, 2] ok, [3, 4] has a missing receipt, 5 never fetched
+        mock.push(serde_json::Value::Null).unwrap();
+        for hash in hashes[..3].iter().rev() {
+            mock.push(TransactionReceipt {
+                transaction_hash: *hash,
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        let mut progress = Vec::new();
+        let err = client
+            .fetch_receipts_batched(&hashes, 2, |done| progress.push(done))
+            .await
+            .unwrap_err();
+
+        assert_eq!(progress, vec![2]);
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::BatchInterrupted { completed: 2 })
+        ));
+    }
+
+    struct HashLeaf;
+
+    impl LeafEncoder for HashLeaf {
+        fn encode(&self, receipt: &TransactionReceipt) -> anyhow::Result<Vec<u8>> {
+            Ok(receipt.transaction_hash.as_bytes().to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn leaf_encoders_control_the_leaves() {
+        let (client, mock) = mocked_client();
+        let block = Block::<TxHash> {
+            transactions: vec![H256::from_low_u64_be(1), H256::from_low_u64_be(2)],
+            ..Default::default()
+        };
+        let receipts: Vec<_> = block
+            .transactions
+            .iter()
+            .zip(0u64..)
+            .map(|(hash, index)| TransactionReceipt {
+                transaction_hash: *hash,
+                transaction_index: index.into(),
+                ..Default::default()
+            })
+            .collect();
+        for receipt in receipts.iter().chain(&receipts).chain(&receipts).rev() {
+            mock.push(receipt.clone()).unwrap();
+        }
+
+        let default = client.get_transaction_merkle(&block).await.unwrap();
+        // the blocking pool path and the inline path agree
+        let inline = client
+            .get_transaction_merkle_with(&block, &DefaultLeafEncoder::default())
+            .await
+            .unwrap();
+        assert_eq!(default.root, inline.root);
+        let today: Vec<_> = receipts
+            .iter()
+            .map(|r| types::receipt_leaf(r).unwrap())
+            .collect();
+        assert_eq!(default.root, MerkleTree::build(&today).root);
+
+        let hashed = client
+            .get_transaction_merkle_with(&block, &HashLeaf)
+            .await
+            .unwrap();
+        let hashes: Vec<_> = block
+            .transactions
+            .iter()
+            .map(|h| h.as_bytes().to_vec())
+            .collect();
+        assert_eq!(hashed.root, MerkleTree::build(&hashes).root);
+    }
+
+    async fn merkle_with_missing_second_receipt(
+        missing_receipts: MissingReceipts,
+    ) -> anyhow::Result<MerkleTree> {
+        let (client, mock) = mocked_client();
+        let client = client.with_missing_receipts(missing_receipts);
+        let block = Block::<TxHash> {
+            transactions: vec![H256::from_low_u64_be(1), H256::from_low_u64_be(2)],
+            ..Default::default()
+        };
+        mock.push(serde_json::Value::Null).unwrap();
+        mock.push(TransactionReceipt {
+            transaction_hash: block.transactions[0],
+            ..Default::default()
+        })
+        .unwrap();
+        client.get_transaction_merkle(&block).await
+    }
+
+    #[tokio::test]
+    async fn missing_receipts_follow_the_configured_policy() {
+        let err = merkle_with_missing_second_receipt(MissingReceipts::Error)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::ReceiptNotFound(hash)) if *hash == H256::from_low_u64_be(2)
+        ));
+
+        let placeholder = merkle_with_missing_second_receipt(MissingReceipts::Placeholder)
+            .await
+            .unwrap();
+        assert_eq!(placeholder.proofs.len(), 2);
+        let leaf = Transaction::placeholder_leaf(&H256::from_low_u64_be(2));
+        assert!(placeholder.root.verify(&leaf, &placeholder.proofs[1]));
+
+        let skipped = merkle_with_missing_second_receipt(MissingReceipts::Skip)
+            .await
+            .unwrap();
+        assert_eq!(skipped.proofs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn receipt_lost_to_a_reorg_refetches_the_replaced_block() {
+        let (client, mock) = mocked_client();
+        let receipt = |n: u64| TransactionReceipt {
+            transaction_hash: H256::from_low_u64_be(n),
+            transaction_index: 0.into(),
+            ..Default::default()
+        };
+        let stale = Block::<TxHash> {
+            number: Some(5.into()),
+            hash: Some(H256::repeat_byte(0xa)),
+            transactions: vec![H256::from_low_u64_be(1), H256::from_low_u64_be(2)],
+            ..Default::default()
+        };
+        let replaced = Block::<TxHash> {
+            hash: Some(H256::repeat_byte(0xb)),
+            transactions: vec![H256::from_low_u64_be(3)],
+            ..stale.clone()
+        };
+        mock.push(receipt(3)).unwrap();
+        mock.push(replaced).unwrap();
+        mock.push(serde_json::Value::Null).unwrap();
+        mock.push(receipt(1)).unwrap();
+
+        let tree = client.get_transaction_merkle(&stale).await.unwrap();
+        let leaf = DefaultLeafEncoder::default().encode(&receipt(3)).unwrap();
+        assert_eq!(tree.root, MerkleTree::build(&[leaf]).root);
+        mock.assert_request("eth_getTransactionReceipt", [H256::from_low_u64_be(1)])
+            .unwrap();
+        mock.assert_request("eth_getTransactionReceipt", [H256::from_low_u64_be(2)])
+            .unwrap();
+        mock.assert_request("eth_getBlockByNumber", ("0x5", false))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn prove_transaction_across_a_reorg_uses_the_replacement_block() {
+        let (client, mock) = mocked_client();
+        let receipt = |n: u64, index: u64| TransactionReceipt {
+            transaction_hash: H256::from_low_u64_be(n),
+            transaction_index: index.into(),
+            ..Default::default()
+        };
+        let stale = Block::<TxHash> {
+            number: Some(5.into()),
+            hash: Some(H256::repeat_byte(0xa)),
+            transactions: vec![H256::from_low_u64_be(1), H256::from_low_u64_be(2)],
+            ..Default::default()
+        };
+        // the replacement moves tx 2 to index 0 and puts tx 3 at index 1
+        let replaced = Block::<TxHash> {
+            hash: Some(H256::repeat_byte(0xb)),
+            transactions: vec![H256::from_low_u64_be(2), H256::from_low_u64_be(3)],
+            ..stale.clone()
+        };
+        // answered newest-first: stale block, its receipts with 1 missing,
+        // the re-read block and the replacement's receipts
+        mock.push(receipt(3, 1)).unwrap();
+        mock.push(receipt(2, 0)).unwrap();
+        mock.push(replaced.clone()).unwrap();
+        mock.push(receipt(2, 1)).unwrap();
+        mock.push(serde_json::Value::Null).unwrap();
+        mock.push(stale).unwrap();
+
+        let proof = client
+            .prove_transaction(5, H256::from_low_u64_be(2))
+            .await
+            .unwrap();
+        let encoder = DefaultLeafEncoder::default();
+        let leaves = vec![
+            encoder.encode(&receipt(2, 0)).unwrap(),
+            encoder.encode(&receipt(3, 1)).unwrap(),
+        ];
+        assert_eq!(proof.transaction_index, 0);
+        assert_eq!(proof.leaf, leaves[0]);
+        assert_eq!(proof.root, MerkleTree::build(&leaves).root);
+        assert!(proof.verify());
+    }
+
+    #[tokio::test]
+    async fn provider_errors_name_the_chain() {
+        let (client, _mock) = mocked_client();
+        let err = client.get_block_count().await.unwrap_err();
+
+        assert_eq!(err.to_string(), "chain test (id 1)");
+        assert!(err.downcast_ref::<ProviderError>().is_some());
+    }
+
+    #[tokio::test]
+    async fn get_logs_at_block_queries_one_block_hash() {
+        let (provider, mock) = Provider::mocked();
+        let addresses = vec![Address::repeat_byte(9)];
+        let client = EthereumClient::from_provider(provider, "test", 1, 0, addresses.clone());
+        let hash = H256::repeat_byte(3);
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+
+        let event = "Transfer(address,address,uint256)";
+        assert!(client
+            .get_logs_at_block(hash, &[event])
+            .await
+            .unwrap()
+            .is_empty());
+
+        let expected = Filter::new()
+            .at_block_hash(hash)
+            .address(addresses)
+            .events([event]);
+        mock.assert_request("eth_getLogs", [expected]).unwrap();
+    }
+
+    #[tokio::test]
+    async fn verify_block_root_compares_with_the_local_root() {
+        let (client, mock) = mocked_client();
+        let receipt = TransactionReceipt {
+            transaction_hash: H256::from_low_u64_be(1),
+            ..Default::default()
+        };
+        let leaf = Transaction::from_receipt(&receipt).to_leaf().unwrap();
+        let expected = MerkleTree::build(&[leaf]).root;
+        let block = Block::<TxHash> {
+            number: Some(3.into()),
+            transactions: vec![receipt.transaction_hash],
+            ..Default::default()
+        };
+
+        for wanted in [expected.clone(), MerkleTreeRoot::new([0; 32])] {
+            mock.push(receipt.clone()).unwrap();
+            mock.push(block.clone()).unwrap();
+            let (matches, root) = client.verify_block_root(3, wanted.clone()).await.unwrap();
+            assert_eq!(matches, wanted == expected);
+            assert_eq!(root, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn prove_transaction_returns_a_verifiable_proof() {
+        let (client, mock) = mocked_client();
+        let receipts: Vec<_> = (0..3u64)
+            .map(|i| TransactionReceipt {
+                transaction_hash: H256::from_low_u64_be(i + 1),
+                transaction_index: i.into(),
+                ..Default::default()
+            })
+            .collect();
+        let block = Block::<TxHash> {
+            number: Some(9.into()),
+            transactions: receipts.iter().map(|r| r.transaction_hash).collect(),
+            ..Default::default()
+        };
+        for receipt in receipts.iter().rev() {
+            mock.push(receipt.clone()).unwrap();
+        }
+        mock.push(block.clone()).unwrap();
+
+        let proof = client
+            .prove_transaction(9, receipts[1].transaction_hash)
+            .await
+            .unwrap();
+        assert_eq!(proof.transaction_index, 1);
+        assert!(proof.verify());
+
+        mock.push(block).unwrap();
+        let err = client
+            .prove_transaction(9, H256::repeat_byte(0xee))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::TransactionNotInBlock {
+                block_number: 9,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn begin_sync_resolves_the_start() {
+        let (provider, mock) = Provider::mocked();
+        let client = EthereumClient::from_provider(provider, "test", 1, 40, vec![]);
+
+        for (start, from) in [
+            (SyncStart::Genesis, 0),
+            (SyncStart::StartBlock, 40),
+            (SyncStart::Block(55), 55),
+        ] {
+            mock.push(U64::from(100)).unwrap();
+            let sync = client.begin_sync(start, &[]).await.unwrap();
+            assert_eq!((sync.from, sync.cur), (from, 97));
+        }
+
+        mock.push(U64::from(101)).unwrap();
+        mock.push(U64::from(100)).unwrap();
+        let sync = client.begin_sync(SyncStart::Latest, &[]).await.unwrap();
+        assert_eq!(sync.from, 97);
+    }
+
+    #[tokio::test]
+    async fn mpt_transaction_proof_checks_the_header_root() {
+        // mainnet USDT transfer
+        let raw = hex::decode("f8aa808512ec276caf83010e2b94dac17f958d2ee523a2206206994597c13d831ec780b844a9059cbb000000000000000000000000fdae129ecc2c27d166a3131098bc05d143fa258e0000000000000000000000000000000000000000000000000000000002faf08025a0c81e70f9e49e0d3b854720143e86d172fecc9e76ef8a8666f2fdc017017c5141a01dd3410180f6a6ca3e25ad3058789cd0df3321ed76b5b4dbe0a2bb2dc28ae274").unwrap();
+        let tx: EtherTransaction = utils::rlp::decode(&raw).unwrap();
+        let block = |transactions_root| Block::<EtherTransaction> {
+            number: Some(7.into()),
+            transactions: vec![tx.clone()],
+            transactions_root,
+            ..Default::default()
+        };
+        let root = Trie::from_ordered(std::slice::from_ref(&raw)).root();
+
+        let (client, mock) = mocked_client();
+        mock.push(block(H256::zero())).unwrap();
+        mock.push(block(root)).unwrap();
+
+        let proof = client.mpt_transaction_proof(7, 0).await.unwrap();
+        assert_eq!((proof.root, proof.value.to_vec()), (root, raw));
+        assert!(proof.verify());
+
+        let err = client.mpt_transaction_proof(7, 0).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::TransactionsRootMismatch { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn wait_for_block_polls_until_the_target() {
+        let (client, mock) = mocked_client();
+        let client = client.with_poll_interval(Duration::from_millis(1));
+        for head in [10u64, 9, 5] {
+            mock.push(U64::from(head)).unwrap();
+        }
+        client
+            .wait_for_block(10, Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        mock.push(U64::from(3)).unwrap();
+        let err = client.wait_for_block(10, Duration::ZERO).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::WaitTimedOut {
+                target: 10,
+                head: 3
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn balances_are_pinned_to_one_block_in_input_order() {
+        let (client, mock) = mocked_client();
+        let addresses = [Address::repeat_byte(1), Address::repeat_byte(2)];
+        mock.push(U256::from(20)).unwrap();
+        mock.push(U256::from(10)).unwrap();
+
+        let balances = client.get_balances(&addresses, 0x64).await.unwrap();
+        assert_eq!(balances, vec![U256::from(10), U256::from(20)]);
+        for address in addresses {
+            mock.assert_request("eth_getBalance", (address, "0x64"))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn receipt_fee_adds_l1_fee_only_on_l2_chains() {
+        let mut receipt = TransactionReceipt {
+            gas_used: Some(21000.into()),
+            ..Default::default()
+        };
+        receipt
+            .other
+            .insert("l1Fee".into(), serde_json::json!("0x64"));
+
+        let fee = |chain| EthereumClient::receipt_fee(chain, &receipt, 2.into()).unwrap();
+        assert_eq!(fee(1), U256::from(42000));
+        assert_eq!(fee(10), U256::from(42100));
+    }
+
+    #[tokio::test]
+    async fn legacy_receipt_fee_falls_back_to_gas_price() {
+        let (client, mock) = mocked_client();
+        let hash = H256::repeat_byte(3);
+        mock.push(EtherTransaction {
+            gas_price: Some(5.into()),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push(TransactionReceipt {
+            gas_used: Some(100.into()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(client.transaction_fee(hash).await.unwrap(), U256::from(500));
+        mock.assert_request("eth_getTransactionReceipt", [hash])
+            .unwrap();
+        mock.assert_request("eth_getTransactionByHash", [hash])
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn earliest_available_block_fails_when_not_even_the_head_is_served() {
+        let (client, mock) = mocked_client();
+        mock.push(serde_json::Value::Null).unwrap();
+        mock.push(U64::from(0)).unwrap();
+
+        let err = client.earliest_available_block().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::BlockNotFound(0))
+        ));
+    }
+
+    #[tokio::test]
+    async fn earliest_available_block_searches_past_pruned_blocks() {
+        let (client, mock) = mocked_client();
+        mock.push(Block::<TxHash>::default()).unwrap();
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32000,
+            message: "pruned history unavailable".into(),
+            data: None,
+        }));
+        mock.push(Block::<TxHash>::default()).unwrap();
+        mock.push(serde_json::Value::Null).unwrap();
+        mock.push(U64::from(10)).unwrap();
+
+        assert_eq!(client.earliest_available_block().await.unwrap(), 4);
+        mock.assert_request("eth_blockNumber", ()).unwrap();
+        for probe in ["0x0", "0x5", "0x3", "0x4"] {
+            mock.assert_request("eth_getBlockByNumber", (probe, false))
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn call_with_overrides_sends_the_state_and_flags_old_nodes() {
+        let (client, mock) = mocked_client();
+        let token = Address::repeat_byte(4);
+        let mut overrides = StateOverride::default();
+        overrides.account(Address::repeat_byte(5)).balance(7.into());
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32602,
+            message: "too many arguments, want at most 2".into(),
+            data: None,
+        }));
+        mock.push::<Bytes, Bytes>(vec![1].into()).unwrap();
+
+        let out = client
+            .call_with_overrides(token, vec![0xaa].into(), &overrides, Some(9))
+            .await
+            .unwrap();
+        assert_eq!(out, Bytes::from(vec![1]));
+        let tx: TypedTransaction = TransactionRequest::new().to(token).data(vec![0xaa]).into();
+        mock.assert_request("eth_call", (&tx, "0x9", &overrides))
+            .unwrap();
+
+        let err = client
+            .call_with_overrides(token, Bytes::new(), &overrides, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::StateOverrideUnsupported)
+        ));
+    }
+
+    #[tokio::test]
+    async fn watch_transaction_follows_it_to_confirmation() {
+        let (client, mock) = mocked_client();
+        let client = client.with_poll_interval(Duration::ZERO);
+        let tx_hash = H256::repeat_byte(7);
+        let receipt = TransactionReceipt {
+            transaction_hash: tx_hash,
+            block_number: Some(5.into()),
+            ..Default::default()
+        };
+        // answered newest-first: pending, mined at the head, one block later
+        mock.push(U64::from(6)).unwrap();
+        mock.push(receipt.clone()).unwrap();
+        mock.push(U64::from(5)).unwrap();
+        mock.push(receipt).unwrap();
+        mock.push(EtherTransaction::default()).unwrap();
+        mock.push(serde_json::Value::Null).unwrap();
+
+        let statuses: Vec<TxStatus> = client
+            .watch_transaction(tx_hash, 1)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(
+            statuses,
+            vec![
+                TxStatus::Pending,
+                TxStatus::Mined(5),
+                TxStatus::Confirmed(5)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn storage_history_is_sorted_and_flags_pruned_state() {
+        let (client, mock) = mocked_client();
+        let (address, slot) = (Address::repeat_byte(1), H256::zero());
+        // answered newest-first, for blocks 30, 10, 20 in that order
+        mock.push(H256::from_low_u64_be(2)).unwrap();
+        mock.push(H256::from_low_u64_be(1)).unwrap();
+        mock.push(H256::from_low_u64_be(3)).unwrap();
+
+        let history = client
+            .get_storage_history(address, slot, &[30, 10, 20])
+            .await
+            .unwrap();
+        assert_eq!(
+            history,
+            vec![
+                (10, H256::from_low_u64_be(1)),
+                (20, H256::from_low_u64_be(2)),
+                (30, H256::from_low_u64_be(3)),
+            ]
+        );
+        mock.assert_request("eth_getStorageAt", (address, "0x0", "0x1e"))
+            .unwrap();
+
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32000,
+            message: "missing trie node 1a2b (path ) state 0x1a2b is not available".into(),
+            data: None,
+        }));
+        let err = client
+            .get_storage_history(address, slot, &[5])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::StateUnavailable(5))
+        ));
+    }
+
+    #[tokio::test]
+    async fn trace_transaction_passes_the_tracer_and_flags_disabled_debug() {
+        let (client, mock) = mocked_client();
+        let tx_hash = H256::repeat_byte(6);
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32601,
+            message: "the method debug_traceTransaction does not exist/is not available".into(),
+            data: None,
+        }));
+        mock.push(serde_json::json!({ "type": "CALL", "calls": [] }))
+            .unwrap();
+
+        let tracer = TraceConfig::CallTracer {
+            only_top_call: true,
+        };
+        let trace = client
+            .trace_transaction(tx_hash, tracer.clone())
+            .await
+            .unwrap();
+        assert_eq!(trace["type"], "CALL");
+        let params = serde_json::json!({
+            "tracer": "callTracer",
+            "tracerConfig": { "onlyTopCall": true },
+        });
+        mock.assert_request("debug_traceTransaction", (tx_hash, params))
+            .unwrap();
+
+        let err = client
+            .trace_transaction(tx_hash, TraceConfig::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::TracingUnavailable)
+        ));
+    }
+
+    #[tokio::test]
+    async fn block_anchor_counts_leaves_and_matches_the_full_tree() {
+        let (client, mock) = mocked_client();
+        let hashes: Vec<_> = (1..=3).map(H256::from_low_u64_be).collect();
+        let receipts: Vec<_> = hashes
+            .iter()
+            .zip(0u64..)
+            .map(|(hash, index)| TransactionReceipt {
+                transaction_hash: *hash,
+                transaction_index: index.into(),
+                ..Default::default()
+            })
+            .collect();
+        for receipt in receipts.iter().rev() {
+            mock.push(receipt.clone()).unwrap();
+        }
+        mock.push(Block::<TxHash> {
+            number: Some(7.into()),
+            transactions: hashes,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let anchor = client.block_anchor(7).await.unwrap();
+        let leaves: Vec<_> = receipts
+            .iter()
+            .map(|r| types::receipt_leaf(r).unwrap())
+            .collect();
+        assert_eq!(anchor.number, 7);
+        assert_eq!(anchor.leaf_count, 3);
+        assert_eq!(anchor.root, MerkleTree::build(&leaves).root);
+    }
+
+    #[tokio::test]
+    async fn block_roots_reports_failed_blocks_separately() {
+        let (client, mock) = mocked_client();
+        let client = client.with_max_concurrency(1);
+        let receipt = TransactionReceipt {
+            transaction_hash: H256::from_low_u64_be(1),
+            ..Default::default()
+        };
+        // answered newest-first: block 7 and its receipt, then a missing block 8
+        mock.push(serde_json::Value::Null).unwrap();
+        mock.push(receipt.clone()).unwrap();
+        mock.push(Block::<TxHash> {
+            number: Some(7.into()),
+            transactions: vec![receipt.transaction_hash],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let roots = client.block_roots(&[7, 8]).await;
+        assert_eq!(roots.len(), 2);
+        assert_eq!(roots[0].0, 7);
+        let leaf = types::receipt_leaf(&receipt).unwrap();
+        assert_eq!(
+            *roots[0].1.as_ref().unwrap(),
+            MerkleTree::build(&[leaf]).root
+        );
+        assert_eq!(roots[1].0, 8);
+        assert!(matches!(
+            roots[1]
+                .1
+                .as_ref()
+                .unwrap_err()
+                .downcast_ref::<ClientError>(),
+            Some(ClientError::BlockNotFound(8))
+        ));
+    }
+
+    #[tokio::test]
+    async fn merkle_where_keeps_only_accepted_receipts_in_order() {
+        let (client, mock) = mocked_client();
+        let hashes: Vec<_> = (1..=3).map(H256::from_low_u64_be).collect();
+        let receipts: Vec<_> = hashes
+            .iter()
+            .zip([1u64, 0, 1])
+            .map(|(hash, status)| TransactionReceipt {
+                transaction_hash: *hash,
+                status: Some(status.into()),
+                ..Default::default()
+            })
+            .collect();
+        for receipt in receipts.iter().rev() {
+            mock.push(receipt.clone()).unwrap();
+        }
+        let block = Block::<TxHash> {
+            transactions: hashes,
+            ..Default::default()
+        };
+
+        let tree = client
+            .get_transaction_merkle_where(&block, |r| r.status == Some(1.into()))
+            .await
+            .unwrap();
+        let leaves: Vec<_> = [&receipts[0], &receipts[2]]
+            .iter()
+            .map(|r| types::receipt_leaf(r).unwrap())
+            .collect();
+        assert_eq!(tree.proofs.len(), 2);
+        assert_eq!(tree.root, MerkleTree::build(&leaves).root);
+    }
+
+    #[tokio::test]
+    async fn ping_times_chain_id_and_reports_failures() {
+        let (client, mock) = mocked_client();
+        mock.push(U256::from(1)).unwrap();
+
+        assert!(client.ping().await.unwrap() < PING_TIMEOUT);
+        mock.assert_request("eth_chainId", ()).unwrap();
+        assert!(!client.healthy().await);
+    }
+
+    #[tokio::test]
+    async fn pending_transaction_count_uses_the_pending_tag() {
+        let (client, mock) = mocked_client();
+        let address = Address::repeat_byte(6);
+        mock.push(TxpoolStatus {
+            pending: 4.into(),
+            queued: 1.into(),
+        })
+        .unwrap();
+        mock.push(U256::from(12)).unwrap();
+
+        assert_eq!(
+            client.get_pending_transaction_count(address).await.unwrap(),
+            U256::from(12)
+        );
+        assert_eq!(
+            client.get_txpool_status().await.unwrap().pending,
+            U64::from(4)
+        );
+        mock.assert_request("eth_getTransactionCount", (address, "pending"))
+            .unwrap();
+        mock.assert_request("txpool_status", ()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn verify_block_chain_reports_the_first_break() {
+        let header = |number: u64, parent: u8| Block::<TxHash> {
+            number: Some(number.into()),
+            hash: Some(H256::repeat_byte(number as u8)),
+            parent_hash: H256::repeat_byte(parent),
+            ..Default::default()
+        };
+        let (client, mock) = mocked_client();
+        for block in [header(8, 0xee), header(7, 6), header(6, 5)] {
+            mock.push(block).unwrap();
+        }
+        assert_eq!(client.verify_block_chain(6, 8).await.unwrap(), Some(8));
+
+        for block in [header(8, 7), header(7, 6), header(6, 5)] {
+            mock.push(block).unwrap();
+        }
+        assert_eq!(client.verify_block_chain(6, 8).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn latest_block_full_asks_for_transaction_bodies() {
+        let (client, mock) = mocked_client();
+        mock.push(serde_json::Value::Null).unwrap();
+        mock.push(Block::<EtherTransaction> {
+            number: Some(9.into()),
+            transactions: vec![EtherTransaction::default()],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let block = client.get_latest_block_full().await.unwrap();
+        assert_eq!(block.transactions.len(), 1);
+        assert!(client.get_latest_block_full().await.is_err());
+        mock.assert_request("eth_getBlockByNumber", ("latest", true))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn pending_block_uses_the_pending_tag() {
+        let (client, mock) = mocked_client();
+        mock.push(serde_json::Value::Null).unwrap();
+
+        assert!(client.get_pending_block().await.unwrap().is_none());
+        mock.assert_request("eth_getBlockByNumber", ("pending", false))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn base_fee_is_none_without_eip1559() {
+        let (client, mock) = mocked_client();
+        mock.push(Block::<TxHash>::default()).unwrap();
+        mock.push(Block::<TxHash> {
+            base_fee_per_gas: Some(7.into()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(client.get_base_fee(1).await.unwrap(), Some(7.into()));
+        assert_eq!(client.get_base_fee(1).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn locate_transaction_reads_the_receipt_position() {
+        let (client, mock) = mocked_client();
+        let tx_hash = H256::repeat_byte(3);
+        mock.push(TransactionReceipt::default()).unwrap();
+        mock.push(serde_json::Value::Null).unwrap();
+        mock.push(TransactionReceipt {
+            block_number: Some(12.into()),
+            transaction_index: 4.into(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            client.locate_transaction(tx_hash).await.unwrap(),
+            Some((12, 4))
+        );
+        assert_eq!(client.locate_transaction(tx_hash).await.unwrap(), None);
+        let err = client.locate_transaction(tx_hash).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::ReceiptWithoutBlock(hash)) if *hash == tx_hash
+        ));
+    }
+
+    #[tokio::test]
+    async fn transaction_logs_keep_the_configured_addresses() {
+        let (provider, mock) = Provider::mocked();
+        let token = Address::repeat_byte(1);
+        let client = EthereumClient::from_provider(provider, "test", 1, 0, vec![token]);
+        let tx_hash = H256::repeat_byte(4);
+        let ours = Log {
+            address: token,
+            ..Default::default()
+        };
+        mock.push(serde_json::Value::Null).unwrap();
+        mock.push(TransactionReceipt {
+            logs: vec![
+                Log {
+                    address: Address::repeat_byte(2),
+                    ..Default::default()
+                },
+                ours.clone(),
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            client.get_transaction_logs(tx_hash).await.unwrap(),
+            vec![ours]
+        );
+        let err = client.get_transaction_logs(tx_hash).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::ReceiptNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn transaction_logs_keep_the_configured_events() {
+        let (client, mock) = mocked_client();
+        let event = "Transfer(address,address,uint256)";
+        let client = client.with_events(&[event]);
+        let transfer = Log {
+            topics: vec![event_topic0(event)],
+            ..Default::default()
+        };
+        mock.push(TransactionReceipt {
+            logs: vec![
+                Log {
+                    topics: vec![H256::repeat_byte(9)],
+                    ..Default::default()
+                },
+                Log::default(),
+                transfer.clone(),
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            client
+                .get_transaction_logs(H256::repeat_byte(4))
+                .await
+                .unwrap(),
+            vec![transfer]
+        );
+    }
+
+    #[tokio::test]
+    async fn confirmed_head_rejects_a_young_chain() {
+        let (client, mock) = mocked_client();
+        let client = client.with_confirmations(5);
+        mock.push(U64::from(4)).unwrap();
+        mock.push(U64::from(12)).unwrap();
+
+        assert_eq!(client.confirmed_head().await.unwrap(), 7);
+        let err = client.new_sync(0, &[]).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::HeightBelowConfirmations {
+                head: 4,
+                confirmations: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn find_missing_blocks_lists_the_gaps() {
+        assert_eq!(
+            EthereumClient::find_missing_blocks(&[7, 3, 3, 5, 12], 3, 8),
+            vec![4, 6, 8]
+        );
+        assert!(EthereumClient::find_missing_blocks(&[1, 2], 1, 2).is_empty());
+        assert!(EthereumClient::find_missing_blocks(&[], 2, 1).is_empty());
+    }
+
+    #[tokio::test]
+    async fn verify_range_complete_reports_unavailable_blocks() {
+        let (client, mock) = mocked_client();
+        // answered newest-first: 12 is missing, 11 comes back as another block
+        mock.push(serde_json::Value::Null).unwrap();
+        mock.push(block_at(9, 0)).unwrap();
+        mock.push(block_at(10, 0)).unwrap();
+
+        assert_eq!(
+            client.verify_range_complete(10, 12).await.unwrap(),
+            vec![11, 12]
+        );
+    }
+
+    #[tokio::test]
+    async fn confirmed_head_consults_the_predicate() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let (client, mock) = mocked_client();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let client = client.with_confirmation_predicate(move |number| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move { number <= 37 })
+        });
+        mock.push(U64::from(100)).unwrap();
+
+        assert_eq!(client.confirmed_head().await.unwrap(), 37);
+        assert!(calls.load(Ordering::SeqCst) <= 16);
+
+        let client = client.with_confirmation_predicate(|_| Box::pin(async { false }));
+        mock.push(U64::from(5)).unwrap();
+        let err = client.confirmed_head().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::NothingConfirmed { head: 5 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn confirmed_head_can_follow_the_finalized_tag() {
+        let (client, mock) = mocked_client();
+        let client = client.with_confirmation_policy(ConfirmationPolicy::Finalized);
+        mock.push(Block::<TxHash> {
+            number: Some(90.into()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(client.confirmed_head().await.unwrap(), 90);
+        mock.assert_request("eth_getBlockByNumber", ("finalized", false))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn metrics_count_calls_and_errors() {
+        let (client, mock) = mocked_client();
+        mock.push(U64::from(7)).unwrap();
+
+        client.get_block_count().await.unwrap();
+        // the mock has no response left
+        client.get_block_count().await.unwrap_err();
+
+        let snapshot = client.clone().metrics_snapshot();
+        let block_number = snapshot.methods["eth_blockNumber"];
+        assert_eq!((block_number.calls, block_number.errors), (2, 1));
+        assert_eq!(snapshot.errors.transport, 1);
+    }
+
+    #[test]
+    fn transactions_root_proof_is_for_the_last_leaf() {
+        let block = Block::<H256> {
+            transactions: vec![H256::repeat_byte(1), H256::repeat_byte(2)],
+            transactions_root: H256::repeat_byte(9),
+            ..Default::default()
+        };
+
+        let (root, proof) = EthereumClient::get_transactions_root_proof(&block);
+        let leaf = EthereumClient::data_slice(&[block.transactions_root]).remove(0);
+        assert!(root.verify(&leaf, &proof));
+
+        let (same_root, first) = EthereumClient::get_hash_merkle(&block, None);
+        assert_eq!(same_root, root);
+        assert!(!root.verify(&leaf, &first));
+    }
+
+    #[test]
+    fn group_logs_by_transaction_orders_groups_and_reports_orphans() {
+        let log = |tx: Option<u64>, index: u64| Log {
+            transaction_hash: tx.map(H256::from_low_u64_be),
+            log_index: Some(index.into()),
+            ..Default::default()
+        };
+        let grouped = EthereumClient::group_logs_by_transaction(vec![
+            log(Some(1), 2),
+            log(Some(2), 1),
+            log(None, 3),
+            log(Some(1), 0),
+        ]);
+
+        assert_eq!(grouped.groups.len(), 2);
+        let first = &grouped.groups[&H256::from_low_u64_be(1)];
+        assert_eq!(first, &vec![log(Some(1), 0), log(Some(1), 2)]);
+        assert_eq!(grouped.without_hash, vec![log(None, 3)]);
+    }
+
+    #[test]
+    fn tag_logs_names_the_matching_signature() {
+        let transfer = "Transfer(address,address,uint256)";
+        let approval = "Approval(address,address,uint256)";
+        let log = |signature: &str| Log {
+            topics: vec![event_topic0(signature)],
+            ..Default::default()
+        };
+        let logs = vec![log(approval), log(transfer), log("Other()"), Log::default()];
+
+        let tagged = EthereumClient::tag_logs(&[transfer, approval], logs.clone());
+        assert_eq!(
+            tagged.matched,
+            vec![
+                (approval.to_string(), logs[0].clone()),
+                (transfer.to_string(), logs[1].clone())
+            ]
+        );
+        assert_eq!(tagged.unmatched, logs[2..].to_vec());
+    }
+
+    #[tokio::test]
+    async fn call_attaches_decoded_revert_reason() {
+        let (client, mock) = mocked_client();
+        let data = "0x08c379a0\
+            0000000000000000000000000000000000000000000000000000000000000020\
+            0000000000000000000000000000000000000000000000000000000000000009\
+            6e6f74206f776e65720000000000000000000000000000000000000000000000";
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: 3,
+            message: "execution reverted: not owner".into(),
+            data: Some(serde_json::Value::String(data.into())),
+        }));
+
+        let err = client
+            .call(&TypedTransaction::default(), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ClientError>(),
+            Some(ClientError::Reverted(revert::RevertReason::Error(message))) if message == "not owner"
+        ));
+    }
+
+    #[test]
+    fn hashes_merkle_proves_bare_transaction_hashes() {
+        let block = Block::<TxHash> {
+            transactions: (1..=5).map(H256::from_low_u64_be).collect(),
+            ..Default::default()
+        };
+        let tree = EthereumClient::hashes_merkle(&block);
+
+        assert_eq!(tree.proofs.len(), 5);
+        for (hash, proof) in block.transactions.iter().zip(&tree.proofs) {
+            assert!(tree.root.verify_prehashed(&hash.0, proof));
+        }
+        assert_ne!(tree.root, EthereumClient::get_hash_merkle(&block, None).0);
+    }
+
+    #[test]
+    fn recover_sender_handles_legacy_and_typed_transactions() {
+        let wallet: LocalWallet =
+            "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+                .parse()
+                .unwrap();
+        let request = TransactionRequest::new()
+            .to(Address::repeat_byte(1))
+            .value(5)
+            .nonce(3)
+            .gas(21000)
+            .gas_price(7)
+            .chain_id(1);
+        let legacy: TypedTransaction = request.into();
+        let dynamic: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(Address::repeat_byte(1))
+            .nonce(3)
+            .gas(21000)
+            .max_fee_per_gas(9)
+            .chain_id(1)
+            .into();
+
+        for typed in [legacy, dynamic] {
+            let signature = wallet.sign_transaction_sync(&typed).unwrap();
+            let raw = typed.rlp_signed(&signature);
+            let mut tx: EtherTransaction = ethers::utils::rlp::decode(&raw).unwrap();
+            assert_eq!(
+                EthereumClient::recover_sender(&tx).unwrap(),
+                wallet.address()
+            );
+
+            tx.from = Address::repeat_byte(2);
+            let err = EthereumClient::recover_sender(&tx).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<ClientError>(),
+                Some(ClientError::SenderMismatch { recovered, .. }) if *recovered == wallet.address()
+            ));
+        }
+    }
+
+    #[test]
+    fn erc20_transfer_filter_sets_tokens_and_topic0() {
+        let tokens = vec![Address::repeat_byte(1), Address::repeat_byte(2)];
+        let filter = EthereumClient::erc20_transfer_filter(&tokens);
+
+        assert_eq!(filter.address, Some(ValueOrArray::Array(tokens)));
+        let topic0: H256 = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+            .parse()
+            .unwrap();
+        assert_eq!(filter.topics[0], Some(ValueOrArray::Value(Some(topic0))));
+    }
+
+    #[tokio::test]
+    async fn empty_code_hashes_to_keccak_of_empty_bytes() {
+        let (client, mock) = mocked_client();
+        mock.push::<Bytes, _>(Bytes::new()).unwrap();
+        mock.push::<Bytes, _>(Bytes::new()).unwrap();
+        let account = Address::repeat_byte(1);
+
+        let expected: H256 = "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+            .parse()
+            .unwrap();
+        assert_eq!(client.get_code_hash(account, None).await.unwrap(), expected);
+        assert_eq!(client.get_code_size(account, None).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn deployed_code_hash_and_size_come_from_eth_get_code() {
+        let (client, mock) = mocked_client();
+        let code = Bytes::from(vec![0x60, 0x80, 0x60, 0x40, 0x52]);
+        mock.push::<Bytes, _>(code.clone()).unwrap();
+        mock.push::<Bytes, _>(code.clone()).unwrap();
+        let account = Address::repeat_byte(1);
+
+        assert_eq!(
+            client.get_code_hash(account, Some(7)).await.unwrap(),
+            H256(ethers::utils::keccak256(&code))
+        );
+        assert_eq!(client.get_code_size(account, Some(7)).await.unwrap(), 5);
+        mock.assert_request("eth_getCode", (account, "0x7"))
+            .unwrap();
     }
 }
@@ -1,3 +1,10 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use anyhow::Context;
+use ethers::abi::{decode, encode, ParamType, Token};
+use ethers::types::Bytes;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use sha3::Keccak256;
 
@@ -5,7 +12,7 @@ pub type MerkleTreeData = Vec<u8>;
 pub type MerkleTreeHash = [u8; 32];
 pub type MerkleTreeProof = Vec<MerkleTreeHash>;
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize)]
 pub struct MerkleTreeRoot {
     pub hash: MerkleTreeHash,
 }
@@ -13,6 +20,23 @@ pub struct MerkleTreeRoot {
 pub struct MerkleTree {
     pub root: MerkleTreeRoot,
     pub proofs: Vec<MerkleTreeProof>,
+    nodes: Vec<MerkleTreeHash>,
+    positional: bool,
+}
+
+/// Whole tree in hex, for verifying proofs client-side (e.g. in a browser).
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct MerkleTreeExport {
+    pub root: String,
+    pub leaves: Vec<String>,
+    pub proofs: Vec<Vec<String>>,
+}
+
+/// `0x` prefixed hex, the form `MerkleTreeExport` uses.
+impl fmt::Display for MerkleTreeRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hash_to_hex(&self.hash))
+    }
 }
 
 impl MerkleTreeRoot {
@@ -21,108 +45,706 @@ impl MerkleTreeRoot {
     }
 
     pub fn verify(&self, data: &MerkleTreeData, proof: &MerkleTreeProof) -> bool {
-        let mut hash = keccak256_array(data);
-        for second_hash in proof {
-            let s = serde_json::to_vec(&sort_hash_pair(&hash, second_hash)).unwrap();
-            hash = keccak256_array(&s);
+        self.verify_with(data, proof, HashAlgo::Keccak256)
+    }
+
+    /// `verify` for a tree built with `MerkleTree::build_with(.., algo)`.
+    pub fn verify_with(
+        &self,
+        data: &MerkleTreeData,
+        proof: &MerkleTreeProof,
+        algo: HashAlgo,
+    ) -> bool {
+        self.hash == fold_proof(algo.hash(data), proof, algo)
+    }
+
+    /// `verify` for a tree from [`MerkleTree::build_with_tag`] with the same
+    /// `tag`.
+    pub fn verify_with_tag(
+        &self,
+        data: &MerkleTreeData,
+        proof: &MerkleTreeProof,
+        tag: &[u8],
+    ) -> bool {
+        self.verify_prehashed(&tagged_leaf_hash(tag, data), proof)
+    }
+
+    /// `verify` for many `(leaf, proof)` pairs, one result per pair.
+    pub fn verify_all(&self, pairs: &[(MerkleTreeData, MerkleTreeProof)]) -> Vec<bool> {
+        pairs
+            .iter()
+            .map(|(data, proof)| self.verify(data, proof))
+            .collect()
+    }
+
+    /// `verify_all` spread over the rayon thread pool.
+    #[cfg(feature = "rayon")]
+    pub fn par_verify_all(&self, pairs: &[(MerkleTreeData, MerkleTreeProof)]) -> Vec<bool> {
+        use rayon::prelude::*;
+
+        pairs
+            .par_iter()
+            .map(|(data, proof)| self.verify(data, proof))
+            .collect()
+    }
+
+    /// Index of the first pair that does not verify.
+    pub fn verify_all_ok(&self, pairs: &[(MerkleTreeData, MerkleTreeProof)]) -> Result<(), usize> {
+        match pairs
+            .iter()
+            .position(|(data, proof)| !self.verify(data, proof))
+        {
+            Some(index) => Err(index),
+            None => Ok(()),
+        }
+    }
+
+    /// `verify` for when only the leaf's hash is at hand.
+    pub fn verify_prehashed(&self, leaf_hash: &MerkleTreeHash, proof: &MerkleTreeProof) -> bool {
+        self.hash == fold_proof(*leaf_hash, proof, HashAlgo::Keccak256)
+    }
+
+    /// `verify_with` for a tree from [`MerkleTree::build_positional`], where
+    /// the proof only holds for the leaf at `index`.
+    ///
+    /// Bit `k` of `index` says which side the leaf's ancestor takes at level
+    /// `k` (0 = left), so a proof for one leaf does not verify at any other
+    /// index and an `index` of `proof.len()` bits or more is rejected.
+    pub fn verify_positional(
+        &self,
+        data: &MerkleTreeData,
+        proof: &MerkleTreeProof,
+        index: usize,
+        algo: HashAlgo,
+    ) -> bool {
+        if proof.len() < usize::BITS as usize && index >> proof.len() != 0 {
+            return false;
+        }
+        let mut ctx = HashCtx::new(algo);
+        let mut hash = ctx.hash(data);
+        for (level, sibling) in proof.iter().enumerate() {
+            hash = if index >> level & 1 == 0 {
+                ctx.hash_ordered(&hash, sibling)
+            } else {
+                ctx.hash_ordered(sibling, &hash)
+            };
         }
         self.hash == hash
     }
 }
 
-pub fn keccak256_array(data: &[u8]) -> MerkleTreeHash {
+/// Keccak leaf hash of `tag ++ data`, see [`MerkleTree::build_with_tag`].
+fn tagged_leaf_hash(tag: &[u8], data: &[u8]) -> MerkleTreeHash {
     let mut hasher = Keccak256::new();
+    hasher.update(tag);
     hasher.update(data);
-    let result = hasher.finalize();
-    let mut output = [0u8; 32];
-    output.copy_from_slice(&result);
-    output
+    hasher.finalize().into()
+}
+
+/// Walks `proof` up from `leaf_hash`, returning the root it implies.
+fn fold_proof(
+    leaf_hash: MerkleTreeHash,
+    proof: &MerkleTreeProof,
+    algo: HashAlgo,
+) -> MerkleTreeHash {
+    let mut ctx = HashCtx::new(algo);
+    let mut hash = leaf_hash;
+    for second_hash in proof {
+        hash = ctx.hash_pair(&hash, second_hash);
+    }
+    hash
+}
+
+/// Inner node over two children: the hash of their sorted pair, JSON encoded.
+fn hash_pair(first: &MerkleTreeHash, second: &MerkleTreeHash, algo: HashAlgo) -> MerkleTreeHash {
+    HashCtx::new(algo).hash_pair(first, second)
+}
+
+/// One hasher and one JSON buffer reused across many hashes, so building a
+/// tree does not set up a fresh hasher and allocate a buffer per node.
+/// Produces exactly the hashes of [`HashAlgo::hash`] and [`hash_pair`].
+struct HashCtx {
+    algo: HashAlgo,
+    keccak: Keccak256,
+    sha: Sha256,
+    buf: Vec<u8>,
+}
+
+impl HashCtx {
+    fn new(algo: HashAlgo) -> Self {
+        Self {
+            algo,
+            keccak: Keccak256::new(),
+            sha: Sha256::new(),
+            buf: Vec::with_capacity(256),
+        }
+    }
+
+    fn hash(&mut self, data: &[u8]) -> MerkleTreeHash {
+        let mut output = [0u8; 32];
+        match self.algo {
+            HashAlgo::Keccak256 => {
+                self.keccak.update(data);
+                output.copy_from_slice(&self.keccak.finalize_reset());
+            }
+            HashAlgo::Sha256 => {
+                self.sha.update(data);
+                output.copy_from_slice(&self.sha.finalize_reset());
+            }
+        }
+        output
+    }
+
+    fn hash_pair(&mut self, first: &MerkleTreeHash, second: &MerkleTreeHash) -> MerkleTreeHash {
+        let (left, right) = sort_hash_pair(first, second);
+        self.hash_ordered(&left, &right)
+    }
+
+    /// `hash_pair` without sorting, for positional trees.
+    fn hash_ordered(&mut self, left: &MerkleTreeHash, right: &MerkleTreeHash) -> MerkleTreeHash {
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.clear();
+        serde_json::to_writer(&mut buf, &(left, right)).unwrap();
+        let hash = self.hash(&buf);
+        self.buf = buf;
+        hash
+    }
 }
 
+/// Hash function used for leaves and inner nodes. Trees default to
+/// `Keccak256`; `Sha256` is what `string_to_crypto_hash` uses.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum HashAlgo {
+    #[default]
+    Keccak256,
+    Sha256,
+}
+
+impl HashAlgo {
+    pub fn hash(&self, data: &[u8]) -> MerkleTreeHash {
+        let mut output = [0u8; 32];
+        match self {
+            HashAlgo::Keccak256 => output.copy_from_slice(&Keccak256::digest(data)),
+            HashAlgo::Sha256 => output.copy_from_slice(&Sha256::digest(data)),
+        }
+        output
+    }
+}
+
+pub fn keccak256_array(data: &[u8]) -> MerkleTreeHash {
+    HashAlgo::Keccak256.hash(data)
+}
+
+/// Orders a pair of sibling hashes smaller first, reading each hash as a
+/// big-endian `uint256`, the same order as Solidity's
+/// `uint256(a) < uint256(b)` or `a < b` on `bytes32`. On-chain verifiers have
+/// to compare the same way or their roots diverge. Comparing the byte arrays
+/// lexicographically is that same big-endian numeric order.
+///
+/// Equal siblings (a duplicated leaf, or two identical subtrees) come out
+/// the same in either order, so such a pair cannot tell which side a proof
+/// came from. Verifiers that treat that as a forgery vector can build with
+/// [`MerkleTree::build_strict`] to reject these trees up front.
 pub fn sort_hash_pair(
     first: &MerkleTreeHash,
     second: &MerkleTreeHash,
 ) -> (MerkleTreeHash, MerkleTreeHash) {
     if first < second {
-        (first.clone(), second.clone())
+        (*first, *second)
     } else {
-        (second.clone(), first.clone())
+        (*second, *first)
     }
 }
 
 impl MerkleTree {
-    pub fn build(items: &Vec<MerkleTreeData>) -> Self {
-        let items_len = items.len();
+    pub fn build(items: &[MerkleTreeData]) -> Self {
+        Self::build_with(items, HashAlgo::Keccak256)
+    }
 
-        let mut items = items.clone();
+    /// `build` hashing leaves, padding and inner nodes with `algo`.
+    pub fn build_with(items: &[MerkleTreeData], algo: HashAlgo) -> Self {
+        Self::build_padded(items, algo, &MerkleTreeData::new())
+    }
+
+    /// `build_with` filling the leaves up to the next power of two with
+    /// `pad` instead of empty bytes, e.g. `&[0; 32]` to match an
+    /// implementation that pads with zero words. Any tree that needs padding
+    /// gets a different root for a different `pad`; exports of such trees
+    /// are not accepted by `from_export`, which assumes the default.
+    pub fn build_padded(items: &[MerkleTreeData], algo: HashAlgo, pad: &[u8]) -> Self {
+        let mut ctx = HashCtx::new(algo);
+        let leaves: Vec<MerkleTreeHash> = items.iter().map(|item| ctx.hash(item)).collect();
+        Self::from_hashed(&leaves, algo, algo.hash(pad))
+    }
+
+    /// `build` hashing every leaf as `keccak256(tag ++ item)`, for one
+    /// verifier contract that anchors several kinds of tree.
+    ///
+    /// The tag is part of the commitment: the root depends on it, and a proof
+    /// only verifies through [`MerkleTreeRoot::verify_with_tag`] with the same
+    /// tag, so a leaf of one tree type cannot be replayed as another. Tags
+    /// should not be prefixes of one another, or `tag ++ item` can be read
+    /// with a shorter tag and a longer item. Padding is untagged, as in
+    /// `build`.
+    pub fn build_with_tag(items: &[MerkleTreeData], tag: &[u8]) -> Self {
+        let leaves: Vec<MerkleTreeHash> = items
+            .iter()
+            .map(|item| tagged_leaf_hash(tag, item))
+            .collect();
+        Self::from_leaf_hashes(&leaves)
+    }
+
+    /// `build` from leaf hashes computed elsewhere, e.g. by several workers:
+    /// `from_leaf_hashes(&hashes)` equals `build(&items)` when every
+    /// `hashes[i]` is `keccak256_array(&items[i])`, proofs included.
+    pub fn from_leaf_hashes(hashes: &[MerkleTreeHash]) -> Self {
+        Self::build_prehashed(hashes, HashAlgo::Keccak256)
+    }
+
+    /// Tree whose leaves are the given 32 byte hashes as they are, without
+    /// hashing them again; check proofs with
+    /// [`MerkleTreeRoot::verify_prehashed`]. Padding and inner nodes use
+    /// `algo` as in `build_with`.
+    pub fn build_prehashed(leaves: &[MerkleTreeHash], algo: HashAlgo) -> Self {
+        Self::from_hashed(leaves, algo, algo.hash(&MerkleTreeData::new()))
+    }
+
+    /// `build_with` that fails if any proof would pair a node with an equal
+    /// sibling, the ambiguity described on [`sort_hash_pair`]. Subtrees made
+    /// only of padding are not on any proof path and are not checked.
+    pub fn build_strict(items: &[MerkleTreeData], algo: HashAlgo) -> anyhow::Result<Self> {
+        let tree = Self::build_with(items, algo);
+        let st_sum = tree.nodes.len() / 2;
+        for leaf in 0..tree.proofs.len() {
+            let mut v = st_sum + leaf;
+            while v > 0 {
+                let w = if v.is_multiple_of(2) { v - 1 } else { v + 1 };
+                anyhow::ensure!(
+                    tree.nodes[v] != tree.nodes[w],
+                    "leaf {} is paired with an equal sibling hash",
+                    leaf
+                );
+                v = (v - 1) >> 1;
+            }
+        }
+        Ok(tree)
+    }
+
+    /// `build_with` hashing every inner node as the `(left, right)` pair of
+    /// its children instead of the `sort_hash_pair` order.
+    ///
+    /// Under sorted pairs a parent is the same whichever side a child is on,
+    /// so the root and a `(leaf, proof)` pair carry nothing about the leaf's
+    /// position: any index below `2^proof.len()` verifies, and only a holder
+    /// of the tree can map a proof back with [`MerkleTree::leaf_index`]. A
+    /// positional tree binds every proof to its index instead; check it with
+    /// [`MerkleTreeRoot::verify_positional`]. Its root differs from the
+    /// sorted one and is not what OpenZeppelin's `MerkleProof` expects.
+    pub fn build_positional(items: &[MerkleTreeData], algo: HashAlgo) -> Self {
+        let mut ctx = HashCtx::new(algo);
+        let leaves: Vec<MerkleTreeHash> = items.iter().map(|item| ctx.hash(item)).collect();
+        Self::from_hashed_with(&leaves, algo, algo.hash(&MerkleTreeData::new()), true)
+    }
+
+    /// Index of the leaf `proof` belongs to, if it is one of this tree's
+    /// proofs for `leaf_hash`. Unlike the root alone this works for sorted
+    /// trees too, since it looks the proof up rather than reconstructing it.
+    pub fn leaf_index(&self, leaf_hash: &MerkleTreeHash, proof: &MerkleTreeProof) -> Option<usize> {
+        self.leaf_hashes()
+            .iter()
+            .zip(&self.proofs)
+            .position(|(leaf, leaf_proof)| leaf == leaf_hash && leaf_proof == proof)
+    }
+
+    fn from_hashed(leaves: &[MerkleTreeHash], algo: HashAlgo, pad: MerkleTreeHash) -> Self {
+        Self::from_hashed_with(leaves, algo, pad, false)
+    }
+
+    fn from_hashed_with(
+        leaves: &[MerkleTreeHash],
+        algo: HashAlgo,
+        pad: MerkleTreeHash,
+        positional: bool,
+    ) -> Self {
+        let items_len = leaves.len();
 
         let mut st_sum = 0_usize;
         let mut st = 1_usize;
 
-        while st < items.len() {
+        while st < items_len {
             st_sum += st;
 
             st <<= 1;
         }
 
-        while items.len() < st_sum + st {
-            items.push(MerkleTreeData::new());
+        let mut nodes = vec![pad; st_sum + st];
+
+        nodes[st_sum..st_sum + items_len].copy_from_slice(leaves);
+
+        let mut ctx = HashCtx::new(algo);
+        let mut i = st_sum;
+
+        while i > 0 {
+            i -= 1;
+
+            let (left, right) = (&nodes[(i << 1) + 1], &nodes[(i + 1) << 1]);
+            nodes[i] = if positional {
+                ctx.hash_ordered(left, right)
+            } else {
+                ctx.hash_pair(left, right)
+            };
         }
 
-        let mut nodes = vec![[0_u8; 32]; st_sum + st];
+        let mut proofs: Vec<MerkleTreeProof> = Vec::new();
 
-        for i in st_sum..st_sum + st {
-            nodes[i] = keccak256_array(&items[i - st_sum]);
+        for i in 0..items_len {
+            proofs.push(sibling_path(&nodes, i))
         }
 
-        let mut i = st_sum.clone();
+        MerkleTree {
+            root: MerkleTreeRoot::new(nodes[0]),
+            proofs,
+            nodes,
+            positional,
+        }
+    }
 
-        while i > 0 {
-            i -= 1;
+    /// Streams every leaf's proof to `writer`, each one rebuilt from the
+    /// retained nodes as it is written.
+    ///
+    /// Format, all integers big-endian: the proof count as `u64`, then per
+    /// proof its hash count as `u32` followed by that many 32 byte hashes.
+    /// Read it back with [`ProofReader`].
+    pub fn write_proofs<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&(self.proofs.len() as u64).to_be_bytes())?;
+        for leaf in 0..self.proofs.len() {
+            let proof = sibling_path(&self.nodes, leaf);
+            writer.write_all(&(proof.len() as u32).to_be_bytes())?;
+            for hash in &proof {
+                writer.write_all(hash)?;
+            }
+        }
+        Ok(())
+    }
 
-            let s = serde_json::to_vec(&sort_hash_pair(&nodes[(i << 1) + 1], &nodes[(i + 1) << 1]))
-                .unwrap();
+    /// Every node of the tree as a flat, complete binary tree.
+    ///
+    /// With `w` leaf slots (the leaf count rounded up to a power of two) the
+    /// slice holds `st_sum = w - 1` inner nodes followed by the `w` leaves:
+    ///
+    /// - index `0` is the root;
+    /// - node `i` has children `(i << 1) + 1` and `(i + 1) << 1`, and parent
+    ///   `(i - 1) >> 1`;
+    /// - leaf `k` lives at `st_sum + k`; slots past the last real leaf hold
+    ///   the padding hash;
+    /// - an inner node is the hash of the JSON encoded, `sort_hash_pair`
+    ///   ordered pair of its children (the plain `(left, right)` pair for
+    ///   `build_positional`).
+    pub fn node_hashes(&self) -> &[MerkleTreeHash] {
+        &self.nodes
+    }
 
-            nodes[i] = keccak256_array(&s);
+    /// Hashes of the real (non-padding) leaves, in leaf order.
+    pub fn leaf_hashes(&self) -> &[MerkleTreeHash] {
+        let offset = self.nodes.len() / 2;
+        &self.nodes[offset..offset + self.proofs.len()]
+    }
+
+    pub fn export(&self) -> MerkleTreeExport {
+        MerkleTreeExport {
+            root: hash_to_hex(&self.root.hash),
+            leaves: self.leaf_hashes().iter().map(hash_to_hex).collect(),
+            proofs: self
+                .proofs
+                .iter()
+                .map(|proof| proof.iter().map(hash_to_hex).collect())
+                .collect(),
         }
+    }
 
-        let get_proof = |index: usize| -> MerkleTreeProof {
-            let mut result = MerkleTreeProof::new();
+    /// The tree in the layout of merkletreejs' `MerkleTree.marshalTree`:
+    /// `options`, `root`, `layers` from the (padded) leaves up to the root,
+    /// and `leaves`, all `0x` hex.
+    ///
+    /// merkletreejs rebuilds the same tree from `leaves` only when configured
+    /// like this crate: `hashLeaves: false` (the leaves are already hashed),
+    /// `sortPairs: true`, `fillDefaultHash` returning `keccak256("")` for
+    /// the padding, and a `hashFn` that hashes a pair the way
+    /// `sort_hash_pair` + JSON does, i.e.
+    /// `b => keccak256(JSON.stringify([[...b.subarray(0, 32)], [...b.subarray(32)]]))`.
+    /// Trees built with another `HashAlgo` or pad need the matching changes.
+    pub fn to_merkletreejs_json(&self) -> serde_json::Value {
+        let hex = |hashes: &[MerkleTreeHash]| hashes.iter().map(hash_to_hex).collect::<Vec<_>>();
+        let mut layers = Vec::new();
+        let mut width = self.nodes.len().div_ceil(2);
+        while width > 0 {
+            layers.push(hex(&self.nodes[width - 1..2 * width - 1]));
+            width /= 2;
+        }
+        serde_json::json!({
+            "options": {
+                "hashLeaves": false,
+                "sortPairs": !self.positional,
+                "sortLeaves": false,
+                "sort": false,
+                "duplicateOdd": false,
+                "isBitcoinTree": false,
+            },
+            "root": hash_to_hex(&self.root.hash),
+            "leaves": layers[0],
+            "layers": layers,
+        })
+    }
 
-            let mut v = index + st_sum;
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self.export()).unwrap()
+    }
 
-            while v > 0 {
-                let w = if v % 2 == 0 { v - 1 } else { v + 1 };
+    /// Rebuilds a tree from its exported leaf hashes, rejecting exports whose
+    /// root or proofs do not match the leaves.
+    pub fn from_export(export: &MerkleTreeExport) -> anyhow::Result<Self> {
+        let leaves = export
+            .leaves
+            .iter()
+            .map(|leaf| hex_to_hash(leaf))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let algo = HashAlgo::Keccak256;
+        let tree = Self::from_hashed(&leaves, algo, algo.hash(&MerkleTreeData::new()));
 
-                result.push(nodes[w]);
+        anyhow::ensure!(
+            tree.export() == *export,
+            "exported root or proofs do not match the leaves"
+        );
+        Ok(tree)
+    }
 
-                v = (v - 1) >> 1;
-            }
+    pub fn from_json(value: &serde_json::Value) -> anyhow::Result<Self> {
+        Self::from_export(&serde_json::from_value(value.clone())?)
+    }
+}
 
-            result
-        };
+/// Siblings from leaf `index` up to the root of the flat tree `nodes`.
+fn sibling_path(nodes: &[MerkleTreeHash], index: usize) -> MerkleTreeProof {
+    let mut result = MerkleTreeProof::new();
+    let mut v = index + nodes.len() / 2;
 
-        let mut proofs: Vec<MerkleTreeProof> = Vec::new();
+    while v > 0 {
+        let w = if v.is_multiple_of(2) { v - 1 } else { v + 1 };
+        result.push(nodes[w]);
+        v = (v - 1) >> 1;
+    }
 
-        for i in 0..items_len {
-            proofs.push(get_proof(i))
+    result
+}
+
+/// Reads proofs written by [`MerkleTree::write_proofs`] one at a time.
+pub struct ProofReader<R> {
+    reader: R,
+    remaining: u64,
+}
+
+impl<R: Read> ProofReader<R> {
+    /// Reads the proof count; the proofs follow through the iterator.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut count = [0; 8];
+        reader.read_exact(&mut count)?;
+        Ok(Self {
+            reader,
+            remaining: u64::from_be_bytes(count),
+        })
+    }
+
+    /// Proofs not read yet.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    fn read_proof(&mut self) -> io::Result<MerkleTreeProof> {
+        let mut len = [0; 4];
+        self.reader.read_exact(&mut len)?;
+        (0..u32::from_be_bytes(len))
+            .map(|_| {
+                let mut hash = MerkleTreeHash::default();
+                self.reader.read_exact(&mut hash)?;
+                Ok(hash)
+            })
+            .collect()
+    }
+}
+
+impl<R: Read> Iterator for ProofReader<R> {
+    type Item = io::Result<MerkleTreeProof>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
         }
+        self.remaining -= 1;
+        Some(self.read_proof())
+    }
+}
 
-        MerkleTree {
-            root: MerkleTreeRoot::new(nodes[0]),
-            proofs,
+/// Append-only tree with the same root as [`MerkleTree::build_with`] over
+/// the leaves pushed so far, without keeping the leaves.
+///
+/// Only the root of every complete subtree on the left edge is retained, so
+/// memory is O(log n) and a push or a `root()` hashes O(log n) nodes. It
+/// cannot produce proofs; rebuild a `MerkleTree` from the leaves for those.
+#[derive(Clone, Debug)]
+pub struct IncrementalMerkleTree {
+    algo: HashAlgo,
+    len: u64,
+    // frontier[k] is the root of the full 2^k leaf subtree when bit k of len is set
+    frontier: Vec<MerkleTreeHash>,
+    padding: MerkleTreeHash,
+}
+
+impl Default for IncrementalMerkleTree {
+    fn default() -> Self {
+        Self::new(HashAlgo::Keccak256)
+    }
+}
+
+impl IncrementalMerkleTree {
+    pub fn new(algo: HashAlgo) -> Self {
+        Self {
+            algo,
+            len: 0,
+            frontier: Vec::new(),
+            padding: algo.hash(&MerkleTreeData::new()),
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.push_hash(self.algo.hash(data))
+    }
+
+    /// Appends an already hashed leaf.
+    pub fn push_hash(&mut self, leaf_hash: MerkleTreeHash) {
+        let mut hash = leaf_hash;
+        let mut level = 0;
+        while self.len >> level & 1 == 1 {
+            hash = hash_pair(&self.frontier[level], &hash, self.algo);
+            level += 1;
+        }
+        if level == self.frontier.len() {
+            self.frontier.push(hash);
+        } else {
+            self.frontier[level] = hash;
+        }
+        self.len += 1;
+    }
+
+    pub fn root(&self) -> MerkleTreeRoot {
+        if self.len.is_power_of_two() {
+            return MerkleTreeRoot::new(self.frontier[self.len.trailing_zeros() as usize]);
+        }
+
+        let depth = self.len.next_power_of_two().trailing_zeros() as usize;
+        let mut hash = self.padding;
+        let mut padding = self.padding;
+        for level in 0..depth {
+            hash = if self.len >> level & 1 == 1 {
+                hash_pair(&self.frontier[level], &hash, self.algo)
+            } else {
+                hash_pair(&hash, &padding, self.algo)
+            };
+            padding = hash_pair(&padding, &padding, self.algo);
         }
+        MerkleTreeRoot::new(hash)
     }
 }
 
-pub fn string_to_crypto_hash(input: &str) -> MerkleTreeHash {
-    let mut hasher = Sha256::new();
-    hasher.update(input);
-    let result = hasher.finalize();
-    let mut crypto_hash: MerkleTreeHash = [0u8; 32];
-    crypto_hash.copy_from_slice(&result);
+pub fn hash_to_hex(hash: &MerkleTreeHash) -> String {
+    format!("0x{}", hex::encode(hash))
+}
+
+/// Parses a 32-byte hash, with or without `0x`.
+pub fn hex_to_hash(input: &str) -> anyhow::Result<MerkleTreeHash> {
+    let bytes = hex_to_leaf(input)?;
+    let len = bytes.len();
+    bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("expected a 32 byte hash, got {} bytes in {:?}", len, input))
+}
+
+/// [`MerkleTreeRoot::verify`] against a root given as hex, with or without
+/// `0x`, e.g. straight from an API response. Malformed hex and roots that are
+/// not exactly 32 bytes are errors rather than a `false`.
+pub fn verify_hex(
+    root_hex: &str,
+    data: &MerkleTreeData,
+    proof: &MerkleTreeProof,
+) -> anyhow::Result<bool> {
+    let root = hex_to_hash(root_hex).context("invalid root")?;
+    Ok(MerkleTreeRoot::new(root).verify(data, proof))
+}
+
+/// ABI encoding of `proof` as a single `bytes32[]` argument, i.e. what
+/// `abi.encode(proof)` produces in Solidity.
+pub fn proof_to_abi(proof: &MerkleTreeProof) -> Bytes {
+    let hashes = proof
+        .iter()
+        .map(|hash| Token::FixedBytes(hash.to_vec()))
+        .collect();
+    Bytes::from(encode(&[Token::Array(hashes)]))
+}
 
-    crypto_hash
+/// Inverse of [`proof_to_abi`].
+pub fn proof_from_abi(data: &[u8]) -> anyhow::Result<MerkleTreeProof> {
+    let param = ParamType::Array(Box::new(ParamType::FixedBytes(32)));
+    match decode(&[param], data)?.pop() {
+        Some(Token::Array(hashes)) => hashes
+            .into_iter()
+            .map(|hash| match hash {
+                Token::FixedBytes(bytes) => bytes
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("proof element is not 32 bytes")),
+                other => anyhow::bail!("unexpected proof element {:?}", other),
+            })
+            .collect(),
+        other => anyhow::bail!("expected a bytes32[], got {:?}", other),
+    }
+}
+
+/// `proof` as a Solidity array literal, `[0x…, 0x…]`, for pasting into tests.
+pub fn proof_to_solidity_literal(proof: &MerkleTreeProof) -> String {
+    let hashes: Vec<String> = proof.iter().map(hash_to_hex).collect();
+    format!("[{}]", hashes.join(", "))
+}
+
+/// Parses a hex leaf, with or without `0x`. Odd-length and non-hex input is rejected.
+pub fn hex_to_leaf(input: &str) -> anyhow::Result<MerkleTreeData> {
+    let digits = input.strip_prefix("0x").unwrap_or(input);
+    hex::decode(digits).with_context(|| format!("invalid hex leaf {:?}", input))
+}
+
+impl MerkleTree {
+    pub fn build_from_hex(items: &[&str]) -> anyhow::Result<Self> {
+        let items = items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| hex_to_leaf(item).with_context(|| format!("leaf {}", i)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self::build(&items))
+    }
+
+    pub fn build_from_bytes(items: &[Bytes]) -> Self {
+        let items: Vec<MerkleTreeData> = items.iter().map(|item| item.to_vec()).collect();
+        Self::build(&items)
+    }
+}
+
+pub fn string_to_crypto_hash(input: &str) -> MerkleTreeHash {
+    HashAlgo::Sha256.hash(input.as_bytes())
 }
 
 #[cfg(test)]
@@ -158,8 +780,8 @@ mod tests {
         println!("root - {:?}", merkle_tree.root);
         println!("proof - {:?} ---  {:?}", merkle_tree.proofs[0], items[0]);
 
-        for i in 0..items.len() {
-            assert!(merkle_tree.root.verify(&items[i], &merkle_tree.proofs[i]));
+        for (item, proof) in items.iter().zip(&merkle_tree.proofs) {
+            assert!(merkle_tree.root.verify(item, proof));
         }
     }
 
@@ -176,6 +798,369 @@ mod tests {
         assert!(!merkle_tree.root.verify(&items[0], &merkle_tree.proofs[2]));
     }
 
+    #[test]
+    fn hex_leaves_match_raw_leaves() {
+        let items = vec![vec![0x01, 0x02], vec![0xab], vec![]];
+        let from_hex = MerkleTree::build_from_hex(&["0x0102", "AB", ""]).unwrap();
+        let from_bytes = MerkleTree::build_from_bytes(
+            &items.iter().cloned().map(Bytes::from).collect::<Vec<_>>(),
+        );
+
+        assert_eq!(from_hex.root, MerkleTree::build(&items).root);
+        assert_eq!(from_bytes.root, from_hex.root);
+    }
+
+    #[test]
+    fn malformed_hex_leaves_are_rejected() {
+        assert!(hex_to_leaf("0x123").is_err());
+        assert!(hex_to_leaf("zz").is_err());
+
+        let err = MerkleTree::build_from_hex(&["00", "0xg0"]).err().unwrap();
+        assert!(format!("{:#}", err).starts_with("leaf 1: invalid hex leaf"));
+    }
+
+    #[test]
+    fn positional_proofs_are_bound_to_their_index() {
+        let items: Vec<MerkleTreeData> = (0..5).map(|i| vec![i]).collect();
+        let algo = HashAlgo::Keccak256;
+        let tree = MerkleTree::build_positional(&items, algo);
+        assert_ne!(tree.root, MerkleTree::build(&items).root);
+
+        for (i, proof) in tree.proofs.iter().enumerate() {
+            for index in 0..8 {
+                assert_eq!(
+                    tree.root.verify_positional(&items[i], proof, index, algo),
+                    index == i
+                );
+            }
+            assert!(!tree.root.verify_positional(&items[i], proof, i + 8, algo));
+        }
+        assert_eq!(tree.to_merkletreejs_json()["options"]["sortPairs"], false);
+    }
+
+    #[test]
+    fn leaf_index_maps_a_proof_back_to_its_leaf() {
+        let items: Vec<MerkleTreeData> = (0..6).map(|i| vec![i]).collect();
+        let tree = MerkleTree::build(&items);
+
+        for (i, proof) in tree.proofs.iter().enumerate() {
+            assert_eq!(tree.leaf_index(&keccak256_array(&items[i]), proof), Some(i));
+        }
+        assert_eq!(
+            tree.leaf_index(&keccak256_array(&items[0]), &tree.proofs[1]),
+            None
+        );
+    }
+
+    #[test]
+    fn tagged_proofs_do_not_verify_under_another_tag() {
+        let items: Vec<MerkleTreeData> = (0..5).map(|i| vec![i]).collect();
+        let receipts = MerkleTree::build_with_tag(&items, b"receipts");
+        let balances = MerkleTree::build_with_tag(&items, b"balances");
+        assert_ne!(receipts.root, balances.root);
+        assert_ne!(receipts.root, MerkleTree::build(&items).root);
+
+        for (i, proof) in receipts.proofs.iter().enumerate() {
+            let mut tagged = b"receipts".to_vec();
+            tagged.extend(&items[i]);
+            assert_eq!(receipts.leaf_hashes()[i], keccak256_array(&tagged));
+
+            assert!(receipts.root.verify_with_tag(&items[i], proof, b"receipts"));
+            assert!(!receipts.root.verify_with_tag(&items[i], proof, b"balances"));
+            assert!(!receipts.root.verify(&items[i], proof));
+            assert!(!balances.root.verify_with_tag(&items[i], proof, b"receipts"));
+        }
+    }
+
+    #[test]
+    fn verify_hex_parses_the_root() {
+        let items: Vec<MerkleTreeData> = (0..3).map(|i| vec![i]).collect();
+        let tree = MerkleTree::build(&items);
+        let root = tree.root.to_string();
+
+        assert!(verify_hex(&root, &items[1], &tree.proofs[1]).unwrap());
+        assert!(verify_hex(&root[2..], &items[1], &tree.proofs[1]).unwrap());
+        assert!(!verify_hex(&root, &items[0], &tree.proofs[1]).unwrap());
+
+        assert!(verify_hex("0xzz", &items[0], &tree.proofs[0]).is_err());
+        let err = verify_hex(&root[..40], &items[0], &tree.proofs[0]).unwrap_err();
+        assert!(format!("{:#}", err).contains("expected a 32 byte hash"));
+    }
+
+    #[test]
+    fn json_export_round_trips() {
+        let items: Vec<MerkleTreeData> = (0..5).map(|i| vec![i]).collect();
+        let tree = MerkleTree::build(&items);
+        let json = tree.to_json();
+
+        assert_eq!(json["leaves"].as_array().unwrap().len(), 5);
+        assert_eq!(json["root"], hash_to_hex(&tree.root.hash));
+        assert_eq!(json["leaves"][0], hash_to_hex(&keccak256_array(&items[0])));
+
+        let rebuilt = MerkleTree::from_json(&json).unwrap();
+        assert_eq!(rebuilt.root, tree.root);
+        assert_eq!(rebuilt.proofs, tree.proofs);
+
+        let mut tampered = tree.export();
+        tampered.root = hash_to_hex(&[0u8; 32]);
+        assert!(MerkleTree::from_export(&tampered).is_err());
+    }
+
+    #[test]
+    fn reused_hash_ctx_matches_fresh_hashes() {
+        for algo in [HashAlgo::Keccak256, HashAlgo::Sha256] {
+            let mut ctx = HashCtx::new(algo);
+            for i in 0..4u8 {
+                let data = vec![i; usize::from(i) * 50];
+                assert_eq!(ctx.hash(&data), algo.hash(&data));
+                let (a, b) = (algo.hash(&[i]), algo.hash(&[i + 1]));
+                let pair = serde_json::to_vec(&sort_hash_pair(&a, &b)).unwrap();
+                assert_eq!(ctx.hash_pair(&a, &b), algo.hash(&pair));
+            }
+        }
+    }
+
+    #[test]
+    fn from_leaf_hashes_matches_build() {
+        let items: Vec<MerkleTreeData> = (0..6).map(|i| vec![i; 3]).collect();
+        let hashes: Vec<_> = items.iter().map(|item| keccak256_array(item)).collect();
+        let tree = MerkleTree::from_leaf_hashes(&hashes);
+        let built = MerkleTree::build(&items);
+
+        assert_eq!(tree.root, built.root);
+        assert_eq!(tree.proofs, built.proofs);
+        assert!(tree.root.verify_prehashed(&hashes[4], &tree.proofs[4]));
+    }
+
+    // testdata/merkletreejs/marshal.json has to be produced with merkletreejs
+    // itself by testdata/merkletreejs/generate.js; it is not derived from
+    // this crate, which is the point of the comparison
+    #[test]
+    #[ignore = "needs testdata/merkletreejs/marshal.json from generate.js"]
+    fn merkletreejs_fixture_matches_the_export() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/testdata/merkletreejs/marshal.json"
+        );
+        let fixture: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+        let items: Vec<MerkleTreeData> = (0..5).map(|i| vec![i]).collect();
+        let json = MerkleTree::build(&items).to_merkletreejs_json();
+        for key in ["root", "leaves", "layers"] {
+            assert_eq!(json[key], fixture[key], "{}", key);
+        }
+    }
+
+    #[test]
+    fn merkletreejs_layers_hash_up_to_the_root() {
+        let items: Vec<MerkleTreeData> = (0..5).map(|i| vec![i]).collect();
+        let tree = MerkleTree::build(&items);
+        let json = tree.to_merkletreejs_json();
+
+        let layers: Vec<Vec<MerkleTreeHash>> = json["layers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|layer| {
+                let layer = layer.as_array().unwrap();
+                layer
+                    .iter()
+                    .map(|h| hex_to_hash(h.as_str().unwrap()).unwrap())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(layers.len(), 4);
+        assert_eq!(&layers[0][..5], tree.leaf_hashes());
+        assert!(layers[0][5..]
+            .iter()
+            .all(|pad| *pad == keccak256_array(&[])));
+        for pair in layers.windows(2) {
+            let parents: Vec<_> = pair[0]
+                .chunks(2)
+                .map(|c| hash_pair(&c[0], &c[1], HashAlgo::Keccak256))
+                .collect();
+            assert_eq!(parents, pair[1]);
+        }
+        assert_eq!(layers[3], vec![tree.root.hash]);
+        assert_eq!(json["root"], hash_to_hex(&tree.root.hash));
+        assert_eq!(json["leaves"], json["layers"][0]);
+        assert_eq!(json["options"]["sortPairs"], true);
+    }
+
+    #[test]
+    fn streamed_proofs_read_back_in_order() {
+        let items: Vec<MerkleTreeData> = (0..7).map(|i| vec![i]).collect();
+        let tree = MerkleTree::build(&items);
+        let mut out = Vec::new();
+        tree.write_proofs(&mut out).unwrap();
+        assert_eq!(out.len(), 8 + 7 * (4 + 3 * 32));
+
+        let reader = ProofReader::new(out.as_slice()).unwrap();
+        assert_eq!(reader.remaining(), 7);
+        let proofs = reader.collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(proofs, tree.proofs);
+
+        let truncated = ProofReader::new(&out[..out.len() - 1]).unwrap();
+        assert!(truncated.last().unwrap().is_err());
+    }
+
+    #[test]
+    fn hash_algorithms_give_distinct_stable_roots() {
+        let items: Vec<MerkleTreeData> = (0..4).map(|i| vec![i]).collect();
+        let keccak = MerkleTree::build_with(&items, HashAlgo::Keccak256);
+        let sha = MerkleTree::build_with(&items, HashAlgo::Sha256);
+
+        assert_eq!(keccak.root, MerkleTree::build(&items).root);
+        assert_ne!(keccak.root, sha.root);
+        assert_eq!(
+            hash_to_hex(&keccak.root.hash),
+            "0x76f343cd17871eb5a1e247c2d112d010606ec0e015ea6bc5949adcac34da48f6"
+        );
+        assert_eq!(
+            hash_to_hex(&sha.root.hash),
+            "0xc1764f5359adb4d3a441cde0e3abdfbc02897982b5102c031bf237a303f24bc5"
+        );
+        assert!(sha
+            .root
+            .verify_with(&items[1], &sha.proofs[1], HashAlgo::Sha256));
+        assert!(!sha.root.verify(&items[1], &sha.proofs[1]));
+    }
+
+    #[test]
+    fn padding_value_only_changes_padded_trees() {
+        let items = vec![vec![0], vec![1], vec![2]];
+        let zero_pad = MerkleTree::build_padded(&items, HashAlgo::Keccak256, &[0; 32]);
+        assert_ne!(zero_pad.root, MerkleTree::build(&items).root);
+        for (item, proof) in items.iter().zip(&zero_pad.proofs) {
+            assert!(zero_pad.root.verify(item, proof));
+        }
+
+        let full = vec![vec![0], vec![1], vec![2], vec![3]];
+        assert_eq!(
+            MerkleTree::build_padded(&full, HashAlgo::Keccak256, &[0; 32]).root,
+            MerkleTree::build(&full).root
+        );
+    }
+
+    #[test]
+    fn node_hashes_follow_the_documented_layout() {
+        let items = vec![vec![0], vec![1], vec![2]];
+        let tree = MerkleTree::build(&items);
+        let nodes = tree.node_hashes();
+        let st_sum = nodes.len() / 2;
+
+        assert_eq!((nodes.len(), st_sum), (7, 3));
+        assert_eq!(nodes[0], tree.root.hash);
+        assert_eq!(&nodes[st_sum..st_sum + 3], tree.leaf_hashes());
+        for i in 0..st_sum {
+            let pair = sort_hash_pair(&nodes[(i << 1) + 1], &nodes[(i + 1) << 1]);
+            assert_eq!(
+                nodes[i],
+                keccak256_array(&serde_json::to_vec(&pair).unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn proofs_encode_as_bytes32_arrays() {
+        let proof = vec![[1u8; 32], [0xab; 32]];
+        let encoded = proof_to_abi(&proof);
+
+        // offset, length, then the elements
+        assert_eq!(encoded.len(), 32 * 4);
+        assert_eq!(encoded[31], 0x20);
+        assert_eq!(encoded[63], 2);
+        assert_eq!(proof_from_abi(&encoded).unwrap(), proof);
+        assert_eq!(
+            proof_to_solidity_literal(&proof[..1].to_vec()),
+            format!("[0x{}]", "01".repeat(32))
+        );
+    }
+
+    #[test]
+    fn strict_builds_reject_equal_siblings() {
+        let duplicated = vec![vec![0], vec![0], vec![2]];
+        let err = MerkleTree::build_strict(&duplicated, HashAlgo::Keccak256)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "leaf 0 is paired with an equal sibling hash"
+        );
+
+        // five leaves leave a padding-only pair, which is fine
+        let distinct: Vec<_> = (0..5u8).map(|i| vec![i]).collect();
+        let strict = MerkleTree::build_strict(&distinct, HashAlgo::Keccak256).unwrap();
+        assert_eq!(strict.root, MerkleTree::build(&distinct).root);
+    }
+
+    #[test]
+    fn incremental_roots_match_full_builds() {
+        let mut incremental = IncrementalMerkleTree::default();
+        let mut items = Vec::new();
+        for i in 0..=17u8 {
+            assert_eq!(
+                incremental.root(),
+                MerkleTree::build(&items).root,
+                "{i} leaves"
+            );
+            items.push(vec![i]);
+            incremental.push(&[i]);
+        }
+    }
+
+    #[test]
+    fn verify_all_reports_each_pair() {
+        let items = vec![vec![0], vec![1], vec![2]];
+        let tree = MerkleTree::build(&items);
+        let mut pairs: Vec<_> = items.iter().cloned().zip(tree.proofs.clone()).collect();
+        assert_eq!(tree.root.verify_all(&pairs), vec![true; 3]);
+        assert_eq!(tree.root.verify_all_ok(&pairs), Ok(()));
+
+        pairs[1].0 = vec![9];
+        assert_eq!(tree.root.verify_all(&pairs), vec![true, false, true]);
+        assert_eq!(tree.root.verify_all_ok(&pairs), Err(1));
+        #[cfg(feature = "rayon")]
+        assert_eq!(
+            tree.root.par_verify_all(&pairs),
+            tree.root.verify_all(&pairs)
+        );
+    }
+
+    #[test]
+    fn roots_key_hash_sets_and_print_as_hex() {
+        let root = MerkleTree::build(&[vec![0], vec![1]]).root;
+        let roots: std::collections::HashSet<_> = [root.clone(), root.clone()].into();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(root.to_string(), hash_to_hex(&root.hash));
+    }
+
+    #[test]
+    fn hash_pairs_sort_big_endian() {
+        let mut high = [0u8; 32];
+        high[0] = 1;
+        let mut low = [0u8; 32];
+        low[31] = 2;
+
+        assert_eq!(sort_hash_pair(&high, &low), (low, high));
+        assert_eq!(sort_hash_pair(&low, &high), (low, high));
+    }
+
+    #[test]
+    fn prehashed_leaves_verify_like_raw_ones() {
+        let items: Vec<MerkleTreeData> = (0..6).map(|i| vec![i]).collect();
+        let tree = MerkleTree::build(&items);
+
+        for (i, item) in items.iter().enumerate() {
+            let leaf_hash = keccak256_array(item);
+            assert!(tree.root.verify_prehashed(&leaf_hash, &tree.proofs[i]));
+            assert!(!tree
+                .root
+                .verify_prehashed(&leaf_hash, &tree.proofs[(i + 1) % 6]));
+        }
+    }
+
     #[test]
     fn test_make_crypt_hash() {
         let s = "abcd".to_string();
@@ -187,6 +1172,69 @@ mod tests {
     }
 }
 
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use proptest::collection::{hash_set, vec};
+    use proptest::prelude::*;
+
+    // distinct, non-empty leaves: identical or empty leaves legitimately share proofs
+    fn leaves() -> impl Strategy<Value = Vec<MerkleTreeData>> {
+        hash_set(vec(any::<u8>(), 1..48), 1..=1024).prop_map(|set| set.into_iter().collect())
+    }
+
+    // random sizes rarely land on the shapes where the last level is odd or just full
+    #[test]
+    fn every_proof_verifies_at_edge_sizes() {
+        for size in [
+            1, 2, 3, 4, 5, 7, 8, 9, 15, 16, 17, 31, 32, 33, 63, 64, 65, 1023, 1024, 1025,
+        ] {
+            let items: Vec<MerkleTreeData> =
+                (0..size as u32).map(|i| i.to_be_bytes().to_vec()).collect();
+            let tree = MerkleTree::build(&items);
+            assert_eq!(tree.proofs.len(), size, "size {size}");
+            for (i, (item, proof)) in items.iter().zip(&tree.proofs).enumerate() {
+                assert!(tree.root.verify(item, proof), "size {size}, leaf {i}");
+                if size > 1 {
+                    let other = &tree.proofs[(i + 1) % size];
+                    assert!(!tree.root.verify(item, other), "size {size}, leaf {i}");
+                }
+            }
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn every_proof_verifies(items in leaves()) {
+            let tree = MerkleTree::build(&items);
+            prop_assert_eq!(tree.proofs.len(), items.len());
+            for (item, proof) in items.iter().zip(&tree.proofs) {
+                prop_assert!(tree.root.verify(item, proof));
+            }
+        }
+
+        #[test]
+        fn no_leaf_verifies_with_another_proof(items in leaves()) {
+            prop_assume!(items.len() > 1);
+            let tree = MerkleTree::build(&items);
+            for i in 0..items.len() {
+                let other = (i + 1) % items.len();
+                prop_assert!(!tree.root.verify(&items[i], &tree.proofs[other]));
+            }
+        }
+
+        #[test]
+        fn byte_order_is_big_endian_numeric_order(a in any::<[u8; 32]>(), b in any::<[u8; 32]>()) {
+            use ethers::types::U256;
+
+            let numeric = U256::from_big_endian(&a) < U256::from_big_endian(&b);
+            prop_assert_eq!(sort_hash_pair(&a, &b) == (a, b), numeric || a == b);
+        }
+    }
+}
+
 #[test]
 fn testx() {
     let s = "abcd";
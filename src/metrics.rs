@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use ethers::providers::{ProviderError, RpcError};
+
+/// Counters for one JSON-RPC method.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct MethodMetrics {
+    pub calls: u64,
+    pub errors: u64,
+    pub total_latency: Duration,
+    pub max_latency: Duration,
+}
+
+impl MethodMetrics {
+    pub fn mean_latency(&self) -> Duration {
+        u32::try_from(self.calls)
+            .ok()
+            .and_then(|calls| self.total_latency.checked_div(calls))
+            .unwrap_or_default()
+    }
+}
+
+/// Failed calls by where they failed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ErrorCounts {
+    /// The node answered with a JSON-RPC error object.
+    pub rpc: u64,
+    /// The response could not be deserialized.
+    pub decode: u64,
+    /// Anything else: connection, HTTP status, transport specific failures.
+    pub transport: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct MetricsSnapshot {
+    pub methods: HashMap<&'static str, MethodMetrics>,
+    pub errors: ErrorCounts,
+}
+
+impl MetricsSnapshot {
+    pub fn total_calls(&self) -> u64 {
+        self.methods.values().map(|m| m.calls).sum()
+    }
+}
+
+/// Request metrics of a client; clones of the client share one instance.
+///
+/// Only calls issued by the client are seen: retries done inside the
+/// transport (e.g. a `RetryClient`) count as a single call whose latency
+/// includes the retries.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    methods: Mutex<HashMap<&'static str, MethodMetrics>>,
+    rpc_errors: AtomicU64,
+    decode_errors: AtomicU64,
+    transport_errors: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record(&self, method: &'static str, latency: Duration, error: Option<&ProviderError>) {
+        {
+            let mut methods = self.methods.lock().unwrap_or_else(|e| e.into_inner());
+            let entry = methods.entry(method).or_default();
+            entry.calls += 1;
+            entry.errors += u64::from(error.is_some());
+            entry.total_latency += latency;
+            entry.max_latency = entry.max_latency.max(latency);
+        }
+
+        let counter = match error {
+            None => return,
+            Some(err) if RpcError::as_error_response(err).is_some() => &self.rpc_errors,
+            Some(ProviderError::SerdeJson(_)) => &self.decode_errors,
+            Some(err) if RpcError::as_serde_error(err).is_some() => &self.decode_errors,
+            Some(_) => &self.transport_errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let methods = self
+            .methods
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        MetricsSnapshot {
+            methods,
+            errors: ErrorCounts {
+                rpc: self.rpc_errors.load(Ordering::Relaxed),
+                decode: self.decode_errors.load(Ordering::Relaxed),
+                transport: self.transport_errors.load(Ordering::Relaxed),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_accumulates_per_method() {
+        let metrics = Metrics::default();
+        metrics.record("eth_getLogs", Duration::from_millis(10), None);
+        metrics.record("eth_getLogs", Duration::from_millis(30), None);
+        let err = ProviderError::CustomError("down".into());
+        metrics.record("eth_blockNumber", Duration::from_millis(5), Some(&err));
+
+        let snapshot = metrics.snapshot();
+        let logs = snapshot.methods["eth_getLogs"];
+        assert_eq!(logs.calls, 2);
+        assert_eq!(logs.errors, 0);
+        assert_eq!(logs.max_latency, Duration::from_millis(30));
+        assert_eq!(logs.mean_latency(), Duration::from_millis(20));
+        assert_eq!(snapshot.methods["eth_blockNumber"].errors, 1);
+        assert_eq!(snapshot.total_calls(), 3);
+        assert_eq!(
+            snapshot.errors,
+            ErrorCounts {
+                transport: 1,
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt;
+
+use ethers::abi::{decode, ParamType, Token};
+use ethers::types::{Bytes, U256};
+
+/// Selector of Solidity's built-in `Error(string)`.
+pub const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of Solidity's built-in `Panic(uint256)`.
+pub const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RevertReason {
+    /// `revert()` / `require(cond)` without a message.
+    Empty,
+    /// `require(cond, "message")` or `revert("message")`.
+    Error(String),
+    /// Compiler inserted check failure, see [`panic_reason`].
+    Panic(U256),
+    /// Custom error; arguments are left ABI encoded.
+    Custom { selector: [u8; 4], args: Bytes },
+    /// Return data too short to carry a selector.
+    Unknown(Bytes),
+}
+
+impl fmt::Display for RevertReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevertReason::Empty => write!(f, "reverted without a reason"),
+            RevertReason::Error(message) => write!(f, "{}", message),
+            RevertReason::Panic(code) => write!(f, "panic 0x{:02x}: {}", code, panic_reason(*code)),
+            RevertReason::Custom { selector, args } => {
+                write!(f, "custom error 0x{} ({})", hex::encode(selector), args)
+            }
+            RevertReason::Unknown(data) => write!(f, "undecodable revert data {}", data),
+        }
+    }
+}
+
+/// Meaning of a Solidity `Panic(uint256)` code.
+pub fn panic_reason(code: U256) -> &'static str {
+    if code > U256::from(u8::MAX) {
+        return "unknown panic code";
+    }
+    match code.as_u32() {
+        0x00 => "generic compiler inserted panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum conversion",
+        0x22 => "incorrectly encoded storage byte array",
+        0x31 => "pop() on an empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to a zero-initialized internal function",
+        _ => "unknown panic code",
+    }
+}
+
+/// Classify the return data of a reverted call.
+pub fn decode_revert(data: &Bytes) -> RevertReason {
+    if data.is_empty() {
+        return RevertReason::Empty;
+    }
+    if data.len() < 4 {
+        return RevertReason::Unknown(data.clone());
+    }
+    let mut selector = [0u8; 4];
+    selector.copy_from_slice(&data[..4]);
+    let args = &data[4..];
+
+    let builtin = match selector {
+        ERROR_SELECTOR => match decode(&[ParamType::String], args).as_deref() {
+            Ok([Token::String(message)]) => Some(RevertReason::Error(message.clone())),
+            _ => None,
+        },
+        PANIC_SELECTOR => match decode(&[ParamType::Uint(256)], args).as_deref() {
+            Ok([Token::Uint(code)]) => Some(RevertReason::Panic(*code)),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    builtin.unwrap_or_else(|| RevertReason::Custom {
+        selector,
+        args: Bytes::from(args.to_vec()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::encode;
+
+    fn with_selector(selector: [u8; 4], tokens: &[Token]) -> Bytes {
+        let mut data = selector.to_vec();
+        data.extend(encode(tokens));
+        Bytes::from(data)
+    }
+
+    #[test]
+    fn decodes_error_string() {
+        let data = with_selector(ERROR_SELECTOR, &[Token::String("not owner".into())]);
+        assert_eq!(
+            decode_revert(&data),
+            RevertReason::Error("not owner".into())
+        );
+    }
+
+    #[test]
+    fn decodes_panic_code() {
+        let data = with_selector(PANIC_SELECTOR, &[Token::Uint(U256::from(0x11))]);
+        let reason = decode_revert(&data);
+
+        assert_eq!(reason, RevertReason::Panic(U256::from(0x11)));
+        assert_eq!(
+            reason.to_string(),
+            "panic 0x11: arithmetic overflow or underflow"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_custom_and_unknown() {
+        let data = with_selector([0xde, 0xad, 0xbe, 0xef], &[Token::Bool(true)]);
+        match decode_revert(&data) {
+            RevertReason::Custom { selector, args } => {
+                assert_eq!(selector, [0xde, 0xad, 0xbe, 0xef]);
+                assert_eq!(args.len(), 32);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert_eq!(decode_revert(&Bytes::new()), RevertReason::Empty);
+        assert!(matches!(
+            decode_revert(&Bytes::from(vec![1, 2])),
+            RevertReason::Unknown(_)
+        ));
+    }
+}
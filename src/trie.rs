@@ -0,0 +1,413 @@
+//! Minimal Merkle-Patricia trie, enough to rebuild a block's
+//! `transactionsRoot` and prove a single transaction under it.
+
+use ethers::types::{Bytes, EIP1186ProofResponse, H256};
+use ethers::utils::rlp::{self, Rlp, RlpStream};
+
+use crate::merkle::keccak256_array;
+
+/// Root of a trie without entries, `keccak256(rlp(""))`.
+pub const EMPTY_TRIE_ROOT: H256 = H256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+]);
+
+/// Proof that `value` is stored under `key` in the trie with root `root`,
+/// in the `eth_getProof` layout: the RLP of every hash referenced node on the
+/// path, root first. Nodes shorter than 32 bytes are inlined in their parent
+/// and do not appear on their own.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TrieProof {
+    pub root: H256,
+    pub key: Bytes,
+    pub value: Bytes,
+    pub nodes: Vec<Bytes>,
+}
+
+impl TrieProof {
+    pub fn verify(&self) -> bool {
+        matches!(lookup(self.root, &self.key, &self.nodes), Ok(Some(value)) if value == self.value[..])
+    }
+}
+
+enum Node {
+    Leaf(Vec<u8>, Vec<u8>),
+    Extension(Vec<u8>, Box<Node>),
+    Branch(Box<[Option<Node>; 16]>, Option<Vec<u8>>),
+}
+
+/// Trie over `(key, value)` entries with distinct keys.
+pub struct Trie {
+    root: Option<Node>,
+}
+
+impl Trie {
+    pub fn new(entries: &[(Vec<u8>, Vec<u8>)]) -> Self {
+        let mut items: Vec<(Vec<u8>, &[u8])> = entries
+            .iter()
+            .map(|(key, value)| (nibbles(key), value.as_slice()))
+            .collect();
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        items.dedup_by(|a, b| a.0 == b.0);
+
+        Self {
+            root: (!items.is_empty()).then(|| build(&items, 0)),
+        }
+    }
+
+    /// Transactions trie of a block: key `rlp(index)`, value the encoded
+    /// transaction as given (typed transactions with their type byte).
+    pub fn from_ordered(values: &[Vec<u8>]) -> Self {
+        let entries: Vec<_> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (rlp::encode(&(i as u64)).to_vec(), value.clone()))
+            .collect();
+        Self::new(&entries)
+    }
+
+    pub fn root(&self) -> H256 {
+        match &self.root {
+            Some(node) => H256(keccak256_array(&encode(node))),
+            None => EMPTY_TRIE_ROOT,
+        }
+    }
+
+    /// Proof for entry `index` of a trie built by `from_ordered`.
+    pub fn prove_index(&self, index: u64) -> Option<TrieProof> {
+        self.prove(&rlp::encode(&index))
+    }
+
+    /// Proof for `key`, `None` when the trie does not contain it.
+    pub fn prove(&self, key: &[u8]) -> Option<TrieProof> {
+        let path = nibbles(key);
+        let mut node = self.root.as_ref()?;
+        let mut depth = 0;
+        let mut nodes = vec![Bytes::from(encode(node))];
+
+        let value = loop {
+            let next = match node {
+                Node::Leaf(rest, value) if path[depth..] == rest[..] => break value,
+                Node::Leaf(..) => return None,
+                Node::Extension(prefix, child) => {
+                    if !path[depth..].starts_with(prefix) {
+                        return None;
+                    }
+                    depth += prefix.len();
+                    child.as_ref()
+                }
+                Node::Branch(children, value) => match path.get(depth) {
+                    None => break value.as_ref()?,
+                    Some(nibble) => {
+                        depth += 1;
+                        children[*nibble as usize].as_ref()?
+                    }
+                },
+            };
+            let encoded = encode(next);
+            if encoded.len() >= 32 {
+                nodes.push(Bytes::from(encoded));
+            }
+            node = next;
+        };
+
+        Some(TrieProof {
+            root: self.root(),
+            key: Bytes::from(key.to_vec()),
+            value: Bytes::from(value.clone()),
+            nodes,
+        })
+    }
+}
+
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+fn build(items: &[(Vec<u8>, &[u8])], depth: usize) -> Node {
+    if let [(key, value)] = items {
+        return Node::Leaf(key[depth..].to_vec(), value.to_vec());
+    }
+
+    // items are sorted, so the first and last key share the common prefix
+    let (first, last) = (&items[0].0, &items[items.len() - 1].0);
+    let common = first[depth..]
+        .iter()
+        .zip(&last[depth..])
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common > 0 {
+        let prefix = first[depth..depth + common].to_vec();
+        return Node::Extension(prefix, Box::new(build(items, depth + common)));
+    }
+
+    let mut children: [Option<Node>; 16] = Default::default();
+    let mut value = None;
+    let mut rest = items;
+    if rest[0].0.len() == depth {
+        value = Some(rest[0].1.to_vec());
+        rest = &rest[1..];
+    }
+    while let Some((key, _)) = rest.first() {
+        let nibble = key[depth];
+        let end = rest
+            .iter()
+            .position(|(key, _)| key[depth] != nibble)
+            .unwrap_or(rest.len());
+        children[nibble as usize] = Some(build(&rest[..end], depth + 1));
+        rest = &rest[end..];
+    }
+    Node::Branch(Box::new(children), value)
+}
+
+/// Hex-prefix encoding of a nibble path.
+fn compact(path: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 } + (path.len() % 2) as u8;
+    let mut out = Vec::with_capacity(path.len() / 2 + 1);
+    let rest = if path.len() % 2 == 1 {
+        out.push((flag << 4) | path[0]);
+        &path[1..]
+    } else {
+        out.push(flag << 4);
+        path
+    };
+    out.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    out
+}
+
+fn encode(node: &Node) -> Vec<u8> {
+    let mut stream;
+    match node {
+        Node::Leaf(path, value) => {
+            stream = RlpStream::new_list(2);
+            stream.append(&compact(path, true));
+            stream.append(value);
+        }
+        Node::Extension(path, child) => {
+            stream = RlpStream::new_list(2);
+            stream.append(&compact(path, false));
+            append_child(&mut stream, child);
+        }
+        Node::Branch(children, value) => {
+            stream = RlpStream::new_list(17);
+            for child in children.iter() {
+                match child {
+                    Some(child) => append_child(&mut stream, child),
+                    None => {
+                        stream.append_empty_data();
+                    }
+                }
+            }
+            match value {
+                Some(value) => stream.append(value),
+                None => stream.append_empty_data(),
+            };
+        }
+    }
+    stream.out().to_vec()
+}
+
+/// Children encoding to less than 32 bytes are inlined, others referenced
+/// by hash.
+fn append_child(stream: &mut RlpStream, child: &Node) {
+    let encoded = encode(child);
+    if encoded.len() < 32 {
+        stream.append_raw(&encoded, 1);
+    } else {
+        stream.append(&keccak256_array(&encoded).as_slice());
+    }
+}
+
+fn parse_compact(encoded: &[u8]) -> Option<(Vec<u8>, bool)> {
+    let (first, rest) = encoded.split_first()?;
+    let flag = first >> 4;
+    let mut path = Vec::with_capacity(rest.len() * 2 + 1);
+    if flag & 1 == 1 {
+        path.push(first & 0x0f);
+    }
+    path.extend(nibbles(rest));
+    Some((path, flag & 2 == 2))
+}
+
+/// Checks the account part of an `eth_getProof` response against a block's
+/// `stateRoot`: `account_proof` must lead from the root to
+/// `keccak256(address)` and the account stored there must carry the
+/// response's nonce, balance, storage hash and code hash. An account the
+/// proof shows to be absent verifies only as an empty account. Malformed or
+/// non-matching proof nodes are errors; storage proofs are not checked.
+pub fn verify_account_proof(
+    proof: &EIP1186ProofResponse,
+    state_root: H256,
+) -> anyhow::Result<bool> {
+    let key = keccak256_array(proof.address.as_bytes());
+    Ok(match lookup(state_root, &key, &proof.account_proof)? {
+        Some(account) => account == account_rlp(proof),
+        None => {
+            let empty_code = H256(keccak256_array(&[]));
+            proof.nonce.is_zero()
+                && proof.balance.is_zero()
+                && (proof.code_hash == empty_code || proof.code_hash.is_zero())
+                && (proof.storage_hash == EMPTY_TRIE_ROOT || proof.storage_hash.is_zero())
+        }
+    })
+}
+
+/// State trie value of an account, `rlp([nonce, balance, storageRoot, codeHash])`.
+fn account_rlp(proof: &EIP1186ProofResponse) -> Vec<u8> {
+    let mut stream = RlpStream::new_list(4);
+    stream.append(&proof.nonce);
+    stream.append(&proof.balance);
+    stream.append(&proof.storage_hash);
+    stream.append(&proof.code_hash);
+    stream.out().to_vec()
+}
+
+/// Walks `nodes`, a proof in the `eth_getProof` layout, from `root` along
+/// `key`. Returns the stored value, or `None` when the proof shows the key
+/// is absent. Fails when the nodes do not hash up to `root` or are not trie
+/// nodes.
+pub fn lookup(root: H256, key: &[u8], nodes: &[Bytes]) -> anyhow::Result<Option<Vec<u8>>> {
+    let path = nibbles(key);
+    let mut depth = 0;
+    let mut nodes = nodes.iter();
+    let mut expected = root.as_bytes().to_vec();
+
+    loop {
+        // the node being walked, either hash referenced or inlined in its parent
+        let mut raw = match nodes.next() {
+            Some(node) if keccak256_array(node).as_slice() == expected.as_slice() => node.to_vec(),
+            Some(_) => anyhow::bail!("proof node at depth {} does not match its hash", depth),
+            None => anyhow::bail!("proof ends before the key at depth {}", depth),
+        };
+
+        loop {
+            let node = Rlp::new(&raw);
+            let next = match node.item_count()? {
+                2 => {
+                    let compact = node.at(0)?.data()?;
+                    let (prefix, leaf) = parse_compact(compact)
+                        .ok_or_else(|| anyhow::anyhow!("empty trie node path"))?;
+                    if !path[depth..].starts_with(&prefix) {
+                        return Ok(None);
+                    }
+                    depth += prefix.len();
+                    if leaf {
+                        if depth != path.len() {
+                            return Ok(None);
+                        }
+                        return Ok(Some(node.at(1)?.data()?.to_vec()));
+                    }
+                    node.at(1)?
+                }
+                17 => match path.get(depth) {
+                    None => {
+                        let value = node.at(16)?.data()?;
+                        return Ok((!value.is_empty()).then(|| value.to_vec()));
+                    }
+                    Some(nibble) => {
+                        depth += 1;
+                        node.at(*nibble as usize)?
+                    }
+                },
+                count => anyhow::bail!("trie node with {} items", count),
+            };
+
+            if next.is_list() {
+                raw = next.as_raw().to_vec();
+                continue;
+            }
+            match next.data()? {
+                [] => return Ok(None),
+                reference if reference.len() == 32 => expected = reference.to_vec(),
+                _ => anyhow::bail!("trie node reference is not a hash"),
+            }
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, value: &str) -> (Vec<u8>, Vec<u8>) {
+        (key.as_bytes().to_vec(), value.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn matches_the_reference_trie_roots() {
+        assert_eq!(Trie::new(&[]).root(), EMPTY_TRIE_ROOT);
+
+        // "dogs" case of the ethereum/tests trie fixtures
+        let trie = Trie::new(&[
+            entry("doe", "reindeer"),
+            entry("dog", "puppy"),
+            entry("dogglesworth", "cat"),
+        ]);
+        assert_eq!(
+            hex::encode(trie.root()),
+            "8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"
+        );
+
+        // "puppy", where "do" ends on a branch value
+        let trie = Trie::new(&[
+            entry("do", "verb"),
+            entry("horse", "stallion"),
+            entry("doge", "coin"),
+            entry("dog", "puppy"),
+        ]);
+        assert_eq!(
+            hex::encode(trie.root()),
+            "5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84"
+        );
+        assert!(trie.prove(b"do").unwrap().verify());
+    }
+
+    #[test]
+    fn account_proofs_check_against_the_state_root() {
+        let account = |byte: u8| EIP1186ProofResponse {
+            address: ethers::types::Address::repeat_byte(byte),
+            balance: (u64::from(byte) * 1_000).into(),
+            nonce: u64::from(byte).into(),
+            storage_hash: EMPTY_TRIE_ROOT,
+            code_hash: H256(keccak256_array(&[])),
+            ..Default::default()
+        };
+        let accounts: Vec<_> = (1..=20).map(account).collect();
+        let entries: Vec<_> = accounts
+            .iter()
+            .map(|a| {
+                (
+                    keccak256_array(a.address.as_bytes()).to_vec(),
+                    account_rlp(a),
+                )
+            })
+            .collect();
+        let trie = Trie::new(&entries);
+
+        let mut proof = accounts[7].clone();
+        proof.account_proof = trie.prove(&entries[7].0).unwrap().nodes;
+        assert!(verify_account_proof(&proof, trie.root()).unwrap());
+
+        proof.balance += 1.into();
+        assert!(!verify_account_proof(&proof, trie.root()).unwrap());
+        assert!(verify_account_proof(&proof, EMPTY_TRIE_ROOT).is_err());
+    }
+
+    #[test]
+    fn proofs_verify_for_every_index() {
+        let values: Vec<Vec<u8>> = (0..200u32).map(|i| i.to_be_bytes().repeat(10)).collect();
+        let trie = Trie::from_ordered(&values);
+
+        for (i, value) in values.iter().enumerate() {
+            let proof = trie.prove_index(i as u64).unwrap();
+            assert_eq!(proof.value.to_vec(), *value);
+            assert!(proof.verify(), "index {i}");
+
+            let mut forged = proof.clone();
+            forged.value = Bytes::from(vec![0xff]);
+            assert!(!forged.verify());
+        }
+        assert!(trie.prove_index(200).is_none());
+    }
+}
@@ -1,13 +1,141 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ethers::utils::rlp::RlpStream;
 use ethers::{
-    abi::Address,
+    abi::{decode, encode, Address, Event, ParamType, Token},
     providers::{Http, Provider},
-    types::Filter,
+    types::{
+        Block, Bytes, Filter, Log, Transaction as EtherTransaction, TransactionReceipt, TxHash,
+        H256, U256, U64,
+    },
 };
+use futures::future::BoxFuture;
 use serde::Serialize;
+use tokio::sync::Semaphore;
+
+use crate::error::ClientError;
+pub use crate::merkle::MerkleTreeRoot;
+use crate::merkle::{self, MerkleTree, MerkleTreeData, MerkleTreeProof};
+use crate::metrics::Metrics;
+
+/// Format version prefixed to every `Transaction` leaf before hashing.
+///
+/// Any change to the fields of `Transaction` or to how they are serialized
+/// changes every root, so it has to ship together with a bump of this value.
+pub const LEAF_VERSION: u8 = 1;
+
+/// Concurrent requests allowed per client unless overridden.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 10;
+
+/// Blocks kept between the head and what a sync treats as final.
+pub const DEFAULT_CONFIRMATIONS: u64 = 3;
+
+/// Widest block range a single `eth_getLogs` request covers unless overridden.
+pub const DEFAULT_LOG_CHUNK_SIZE: u64 = 50000;
+
+/// Pause between head polls while waiting for the chain to advance.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Blocks covered by one `fetch_event` window.
+pub const DEFAULT_SYNC_WINDOW: u64 = 50000;
+
+/// Longest `EthereumClient::ping` waits for an answer.
+pub const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Times a block's receipts are refetched after a reorg replaced the block
+/// mid-fetch.
+pub const MAX_REORG_RETRIES: u32 = 3;
+
+/// How long `EthereumClient::watch_transaction` waits for a transaction that
+/// is neither mined nor in the node's pool before reporting it dropped.
+pub const TX_DROP_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Chains whose receipts carry an `l1Fee` field, the L1 data fee paid on top
+/// of the L2 execution fee: OP Mainnet (10), Base (8453), Zora (7777777),
+/// Scroll (534352) and the OP Sepolia (11155420) and Base Sepolia (84532)
+/// testnets. Arbitrum folds the L1 cost into `gasUsed` and needs no special
+/// case.
+pub const L1_FEE_CHAINS: &[u64] = &[10, 8453, 7777777, 534352, 11155420, 84532];
+
+/// Per-address balance, nonce, code and storage overrides for
+/// `EthereumClient::call_with_overrides`.
+pub type StateOverride = ethers::types::spoof::State;
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
-pub struct MerkleTreeRoot {
-    pub hash: [u8; 32],
+/// EIP-2718 transaction type.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub enum TxKind {
+    /// Type 0; also used when the node reports no type at all.
+    Legacy,
+    /// Type 1, EIP-2930.
+    AccessList,
+    /// Type 2, EIP-1559.
+    DynamicFee,
+    Other(u64),
+}
+
+impl TxKind {
+    pub fn from_type(transaction_type: Option<U64>) -> Self {
+        match transaction_type.map(|t| t.as_u64()) {
+            None | Some(0) => TxKind::Legacy,
+            Some(1) => TxKind::AccessList,
+            Some(2) => TxKind::DynamicFee,
+            Some(other) => TxKind::Other(other),
+        }
+    }
+
+    pub fn type_number(&self) -> u64 {
+        match self {
+            TxKind::Legacy => 0,
+            TxKind::AccessList => 1,
+            TxKind::DynamicFee => 2,
+            TxKind::Other(other) => *other,
+        }
+    }
+}
+
+pub fn transaction_kind(tx: &EtherTransaction) -> TxKind {
+    TxKind::from_type(tx.transaction_type)
+}
+
+/// Opt-in fields of the `Transaction` leaf. Everything defaults to off, which
+/// keeps the leaf encoding, and so every root, as it was. Enabling a field
+/// changes the roots; verifiers have to use the same options.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct LeafOptions {
+    pub tx_kind: bool,
+    /// Adds the receipt's block number next to the `block_hash` every leaf
+    /// already carries. Both are part of the committed leaf bytes, so a proof
+    /// only verifies against the root of the block it was built for, even if
+    /// the same transaction shows up in another block's tree; the number
+    /// lets a verifier check that without resolving the hash.
+    pub block_number: bool,
+    pub codec: LeafCodec,
+}
+
+/// Serialization of the `Transaction` leaf after the [`LEAF_VERSION`] byte.
+///
+/// The same receipt gives different leaf bytes, and so a different root,
+/// under each codec, and the leaf does not record which one was used:
+/// verifiers have to know it. Only `Json` matches roots built before codecs
+/// existed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LeafCodec {
+    #[default]
+    Json,
+    /// RLP list of the fields in declaration order, with `kind` appended as
+    /// its type number and then `block_number` when enabled. Smaller and
+    /// cheaper to produce than JSON.
+    Rlp,
+    /// bincode 1.x with its default options (little-endian fixed-width
+    /// integers, `u64` length prefixes) over the fields in declaration
+    /// order; disabled optional fields are left out. Needs the `bincode`
+    /// feature. Compact, but not something on-chain verifiers can decode.
+    #[cfg(feature = "bincode")]
+    Bincode,
 }
 
 #[derive(Serialize)]
@@ -20,28 +148,1338 @@ pub struct Transaction {
     pub block_hash: String,
     pub root: String,
     pub logs_bloom: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<TxKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
 }
 
-#[derive(Clone)]
+impl Transaction {
+    pub fn from_receipt(receipt: &TransactionReceipt) -> Self {
+        Self::from_receipt_with(receipt, &LeafOptions::default())
+    }
+
+    pub fn from_receipt_with(receipt: &TransactionReceipt, options: &LeafOptions) -> Self {
+        Transaction {
+            tx_hash: serde_json::to_string(&receipt.transaction_hash).unwrap(),
+            index: receipt.transaction_index.as_u64(),
+            logs: receipt
+                .logs
+                .iter()
+                .map(|f| serde_json::to_string(f).unwrap())
+                .collect(),
+            from: format!("{:?}", receipt.from),
+            to: format!("{:?}", receipt.to),
+            block_hash: format!("{:?}", receipt.block_hash),
+            root: receipt.root.unwrap_or_default().to_string(),
+            logs_bloom: receipt.logs_bloom.to_string(),
+            kind: options
+                .tx_kind
+                .then(|| TxKind::from_type(receipt.transaction_type)),
+            block_number: options
+                .block_number
+                .then(|| receipt.block_number.unwrap_or_default().as_u64()),
+        }
+    }
+
+    /// Stand-in leaf for a transaction whose receipt is missing:
+    /// [`LEAF_VERSION`] followed by the raw 32 byte transaction hash.
+    pub fn placeholder_leaf(tx_hash: &TxHash) -> Vec<u8> {
+        let mut leaf = vec![LEAF_VERSION];
+        leaf.extend_from_slice(tx_hash.as_bytes());
+        leaf
+    }
+
+    /// Leaf bytes: [`LEAF_VERSION`] followed by the JSON encoding.
+    pub fn to_leaf(&self) -> anyhow::Result<Vec<u8>> {
+        self.to_leaf_with(LeafCodec::Json)
+    }
+
+    /// [`LEAF_VERSION`] followed by the encoding `codec` gives.
+    pub fn to_leaf_with(&self, codec: LeafCodec) -> anyhow::Result<Vec<u8>> {
+        let mut leaf = vec![LEAF_VERSION];
+        match codec {
+            LeafCodec::Json => serde_json::to_writer(&mut leaf, self)?,
+            LeafCodec::Rlp => leaf.extend_from_slice(&self.rlp()),
+            #[cfg(feature = "bincode")]
+            LeafCodec::Bincode => bincode::serialize_into(&mut leaf, self)?,
+        }
+        Ok(leaf)
+    }
+
+    fn rlp(&self) -> Vec<u8> {
+        let optional = usize::from(self.kind.is_some()) + usize::from(self.block_number.is_some());
+        let mut stream = RlpStream::new_list(8 + optional);
+        stream.append(&self.tx_hash);
+        stream.append(&self.index);
+        stream.append_list::<String, _>(&self.logs);
+        stream.append(&self.from);
+        stream.append(&self.to);
+        stream.append(&self.block_hash);
+        stream.append(&self.root);
+        stream.append(&self.logs_bloom);
+        if let Some(kind) = self.kind {
+            stream.append(&kind.type_number());
+        }
+        if let Some(block_number) = self.block_number {
+            stream.append(&block_number);
+        }
+        stream.out().to_vec()
+    }
+}
+
+/// Leaf the client builds for `receipt` under the default `LeafOptions`, for
+/// assembling trees from receipts fetched elsewhere.
+pub fn receipt_leaf(receipt: &TransactionReceipt) -> anyhow::Result<Vec<u8>> {
+    Transaction::from_receipt(receipt).to_leaf()
+}
+
+pub fn receipt_leaf_hash(receipt: &TransactionReceipt) -> merkle::MerkleTreeHash {
+    let leaf = receipt_leaf(receipt).expect("leaf fields always serialize");
+    merkle::keccak256_array(&leaf)
+}
+
+/// Encodes a receipt into the bytes hashed as its leaf in a block's tree.
+pub trait LeafEncoder {
+    fn encode(&self, receipt: &TransactionReceipt) -> anyhow::Result<Vec<u8>>;
+}
+
+/// The crate's own versioned `Transaction` leaf, see [`Transaction::to_leaf`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct DefaultLeafEncoder {
+    pub options: LeafOptions,
+}
+
+impl LeafEncoder for DefaultLeafEncoder {
+    fn encode(&self, receipt: &TransactionReceipt) -> anyhow::Result<Vec<u8>> {
+        Transaction::from_receipt_with(receipt, &self.options).to_leaf_with(self.options.codec)
+    }
+}
+
+/// Rejects leaves written under a different [`LEAF_VERSION`], whose proofs
+/// cannot be checked against roots built by this version of the crate.
+pub fn check_leaf_version(leaf: &[u8]) -> anyhow::Result<()> {
+    match leaf.first() {
+        Some(&LEAF_VERSION) => Ok(()),
+        found => Err(ClientError::UnsupportedLeafVersion {
+            found: found.copied(),
+            expected: LEAF_VERSION,
+        }
+        .into()),
+    }
+}
+
+/// What tree construction does when a block's transaction has no receipt,
+/// e.g. because a reorg dropped it between fetching the block and the receipt.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MissingReceipts {
+    /// Fail with `ClientError::ReceiptNotFound`.
+    #[default]
+    Error,
+    /// Use `Transaction::placeholder_leaf` so the tree keeps one leaf per
+    /// transaction of the block.
+    Placeholder,
+    /// Leave the transaction out. The tree then covers fewer transactions
+    /// than the block and its root will not match other builders'.
+    Skip,
+}
+
+/// Bound on the number of provider requests the concurrent fetch helpers keep
+/// in flight. Clones share the same permits.
+#[derive(Clone, Debug)]
+pub struct RequestLimiter {
+    permits: Arc<Semaphore>,
+    max: usize,
+}
+
+impl RequestLimiter {
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(max)),
+            max,
+        }
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.max - self.permits.available_permits()
+    }
+
+    /// Runs `fut` once a permit is available.
+    pub async fn run<F: Future>(&self, fut: F) -> F::Output {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("limiter is never closed");
+        fut.await
+    }
+}
+
+impl Default for RequestLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_CONCURRENCY)
+    }
+}
+
+/// Logs of one fetch window, split by the provider's `removed` flag. Removed
+/// logs were invalidated by a reorg and should be undone by the caller.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct FetchedLogs {
+    pub added: Vec<Log>,
+    pub removed: Vec<Log>,
+}
+
+impl FetchedLogs {
+    pub fn split(logs: Vec<Log>) -> Self {
+        let (removed, added) = logs.into_iter().partition(|log| log.removed == Some(true));
+        FetchedLogs { added, removed }
+    }
+}
+
+/// Identity of a log on a given chain history, `(block_hash, log_index)`.
+pub type LogKey = (H256, U256);
+
+/// `None` for pending logs, which have no position yet.
+pub fn log_key(log: &Log) -> Option<LogKey> {
+    Some((log.block_hash?, log.log_index?))
+}
+
+/// Drops logs already seen among the last `capacity` added ones, for syncs
+/// that may re-read a range (e.g. resuming from a stale checkpoint).
+/// A reorg removal forgets its key so the log can be added again later.
+#[derive(Clone, Debug)]
+pub struct LogDedup {
+    capacity: usize,
+    seen: HashSet<LogKey>,
+    order: VecDeque<LogKey>,
+}
+
+impl LogDedup {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn filter(&mut self, logs: FetchedLogs) -> FetchedLogs {
+        for key in logs.removed.iter().filter_map(log_key) {
+            if self.seen.remove(&key) {
+                self.order.retain(|seen| *seen != key);
+            }
+        }
+        let added = logs
+            .added
+            .into_iter()
+            .filter(|log| match log_key(log) {
+                Some(key) => self.insert(key),
+                None => true,
+            })
+            .collect();
+        FetchedLogs {
+            added,
+            removed: logs.removed,
+        }
+    }
+
+    fn insert(&mut self, key: LogKey) -> bool {
+        if !self.seen.insert(key) {
+            return false;
+        }
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// What `EthereumClient::confirmed_head` treats as the newest block safe to
+/// sync up to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConfirmationPolicy {
+    /// `head - n` blocks.
+    Depth(u64),
+    /// The node's `safe` block.
+    Safe,
+    /// The node's `finalized` block, the usual choice on L2s.
+    Finalized,
+}
+
+impl Default for ConfirmationPolicy {
+    fn default() -> Self {
+        ConfirmationPolicy::Depth(DEFAULT_CONFIRMATIONS)
+    }
+}
+
+/// Whether a block counts as confirmed, decided by the caller from any signal
+/// (an oracle, a checkpoint contract). Set with
+/// `EthereumClient::with_confirmation_predicate`.
+pub type ConfirmationPredicate = Arc<dyn Fn(u64) -> BoxFuture<'static, bool> + Send + Sync>;
+
+/// Tracer `EthereumClient::trace_transaction` asks the node to run.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub enum TraceConfig {
+    /// Geth's default opcode level trace: `gas`, `failed`, `returnValue`
+    /// and `structLogs`.
+    #[default]
+    StructLogs,
+    /// `callTracer`'s nested call frames; `only_top_call` skips subcalls.
+    CallTracer { only_top_call: bool },
+    /// Any other built-in tracer by name, e.g. `prestateTracer`, with its
+    /// default configuration.
+    Named(String),
+}
+
+impl TraceConfig {
+    /// Options object passed as the second `debug_traceTransaction` param.
+    pub fn to_params(&self) -> serde_json::Value {
+        match self {
+            TraceConfig::StructLogs => serde_json::json!({}),
+            TraceConfig::CallTracer { only_top_call } => serde_json::json!({
+                "tracer": "callTracer",
+                "tracerConfig": { "onlyTopCall": only_top_call },
+            }),
+            TraceConfig::Named(tracer) => serde_json::json!({ "tracer": tracer }),
+        }
+    }
+}
+
+/// A log range bound for `EthereumClient::get_logs_spec`; tags are resolved
+/// to numbers against the chain before querying.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlockSpec {
+    Earliest,
+    Latest,
+    Finalized,
+    Number(u64),
+}
+
+/// Where `EthereumClient::begin_sync` starts reading from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SyncStart {
+    /// Block 0.
+    Genesis,
+    /// The client's configured `start_block`.
+    StartBlock,
+    /// The confirmed head (see [`ConfirmationPolicy`]), skipping history.
+    Latest,
+    Block(u64),
+}
+
+/// Topic0 of a human-readable event signature such as
+/// `"Transfer(address,address,uint256)"`: the keccak hash of the signature,
+/// which must be written without parameter names or spaces.
+pub fn event_topic0(signature: &str) -> H256 {
+    H256(merkle::keccak256_array(signature.as_bytes()))
+}
+
+/// Decodes `log` as an emission of `event`, one `Token` per parameter in
+/// declaration order.
+///
+/// Indexed parameters come from the topics, after topic0 unless the event is
+/// anonymous; the rest are ABI decoded from the data. Indexed strings,
+/// bytes, arrays and tuples are stored as the keccak hash of their value,
+/// which cannot be reversed: they decode to that hash as a 32 byte
+/// `Token::FixedBytes`.
+pub fn decode_log_with_abi(log: &Log, event: &Event) -> anyhow::Result<Vec<Token>> {
+    let mut topics = log.topics.iter();
+    if !event.anonymous {
+        let topic0 = topics.next().copied();
+        anyhow::ensure!(
+            topic0 == Some(event.signature()),
+            "log topic0 {:?} is not the `{}` event",
+            topic0,
+            event.name
+        );
+    }
+
+    let indexed = event.inputs.iter().filter(|input| input.indexed).count();
+    anyhow::ensure!(
+        topics.len() == indexed,
+        "`{}` has {} indexed parameters but the log carries {} topics for them",
+        event.name,
+        indexed,
+        topics.len()
+    );
+
+    let data_types: Vec<ParamType> = event
+        .inputs
+        .iter()
+        .filter(|input| !input.indexed)
+        .map(|input| input.kind.clone())
+        .collect();
+    let mut data = decode(&data_types, &log.data)?.into_iter();
+
+    event
+        .inputs
+        .iter()
+        .map(|input| {
+            if !input.indexed {
+                return Ok(data.next().expect("one token per data type"));
+            }
+            let topic = topics.next().expect("topic count checked above");
+            Ok(match input.kind {
+                ParamType::Address
+                | ParamType::Bool
+                | ParamType::Int(_)
+                | ParamType::Uint(_)
+                | ParamType::FixedBytes(_) => {
+                    decode(std::slice::from_ref(&input.kind), topic.as_bytes())?.remove(0)
+                }
+                _ => Token::FixedBytes(topic.as_bytes().to_vec()),
+            })
+        })
+        .collect()
+}
+
+/// Logs paired with the event signature whose topic0 they carry. Logs that
+/// match none of the signatures (a misbehaving provider) land in `unmatched`.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct TaggedLogs {
+    pub matched: Vec<(String, Log)>,
+    pub unmatched: Vec<Log>,
+}
+
+/// Logs grouped by the transaction that emitted them, each group in
+/// `log_index` order. Logs without a `transaction_hash` (pending logs or a
+/// misbehaving provider) are kept apart in `without_hash`.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct LogsByTransaction {
+    pub groups: HashMap<TxHash, Vec<Log>>,
+    pub without_hash: Vec<Log>,
+}
+
+/// Row format of [`EthereumClient::export_logs`].
+///
+/// Both carry `block_number`, `transaction_hash`, `log_index`, `address`,
+/// `topics` and `data`, hashes and data as `0x` hex. CSV starts with a header
+/// line and joins the topics with `;`; JSONL writes one object per line with
+/// `topics` as an array. Fields a pending log lacks are empty (CSV) or `null`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+impl ExportFormat {
+    pub(crate) fn write_header<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            ExportFormat::Csv => {
+                writeln!(
+                    writer,
+                    "block_number,transaction_hash,log_index,address,topics,data"
+                )
+            }
+            ExportFormat::Jsonl => Ok(()),
+        }
+    }
+
+    pub(crate) fn write_log<W: Write>(&self, writer: &mut W, log: &Log) -> io::Result<()> {
+        let topics: Vec<String> = log
+            .topics
+            .iter()
+            .map(|topic| format!("{:?}", topic))
+            .collect();
+        let data = format!("0x{}", hex::encode(&log.data));
+        match self {
+            ExportFormat::Csv => {
+                let or_empty = |value: Option<String>| value.unwrap_or_default();
+                writeln!(
+                    writer,
+                    "{},{},{},{:?},{},{}",
+                    or_empty(log.block_number.map(|number| number.to_string())),
+                    or_empty(log.transaction_hash.map(|hash| format!("{:?}", hash))),
+                    or_empty(log.log_index.map(|index| index.to_string())),
+                    log.address,
+                    topics.join(";"),
+                    data
+                )
+            }
+            ExportFormat::Jsonl => {
+                let row = serde_json::json!({
+                    "block_number": log.block_number.map(|number| number.as_u64()),
+                    "transaction_hash": log.transaction_hash,
+                    "log_index": log.log_index.map(|index| index.as_u64()),
+                    "address": log.address,
+                    "topics": topics,
+                    "data": data,
+                });
+                serde_json::to_writer(&mut *writer, &row)?;
+                writeln!(writer)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct SyncData {
     pub cur: u64,
     pub from: u64,
     pub n: u64,
     pub filters: Filter,
+    /// Confirmation depth of a `ConfirmationPolicy::Depth` client, 0 for
+    /// tag based policies.
     pub gap: u64,
+    /// Applied to every `fetch_event` batch when set.
+    pub dedup: Option<LogDedup>,
+}
+
+impl SyncData {
+    /// A sync over nothing but `from..=from` with the default window and
+    /// depth and an unrestricted filter, for tests and hand-built syncs. The
+    /// poll interval is configured on the client, not here.
+    pub fn empty(from: u64) -> Self {
+        Self {
+            cur: from,
+            from,
+            n: DEFAULT_SYNC_WINDOW,
+            filters: Filter::new(),
+            gap: DEFAULT_CONFIRMATIONS,
+            dedup: None,
+        }
+    }
+
+    /// Drops logs seen among the last `capacity` ones from later batches.
+    pub fn with_dedup(mut self, capacity: usize) -> Self {
+        self.dedup = Some(LogDedup::new(capacity));
+        self
+    }
+
+    /// How far `from` trails `cur`, in blocks and in `n` wide windows.
+    pub fn progress(&self) -> SyncProgress {
+        let remaining_blocks = self.cur.saturating_sub(self.from);
+        SyncProgress {
+            from: self.from,
+            cur: self.cur,
+            remaining_blocks,
+            estimated_windows: remaining_blocks.div_ceil(self.n.max(1)),
+        }
+    }
 }
 
+/// A transaction's state as reported by `EthereumClient::watch_transaction`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TxStatus {
+    /// Known to the node but not mined.
+    Pending,
+    /// Included in this block, not yet buried under the confirmations.
+    Mined(u64),
+    /// Included in this block with enough confirmations; final.
+    Confirmed(u64),
+    /// Neither mined nor known to the node for `TX_DROP_TIMEOUT`; final.
+    Dropped,
+}
+
+/// Turns successive snapshots of a transaction into `TxStatus` transitions.
+#[derive(Clone, Debug)]
+pub(crate) struct TxTracker {
+    confirmations: u64,
+    drop_timeout: Duration,
+    last: Option<TxStatus>,
+    missing_since: Option<Instant>,
+}
+
+impl TxTracker {
+    pub(crate) fn new(confirmations: u64, drop_timeout: Duration) -> Self {
+        Self {
+            confirmations,
+            drop_timeout,
+            last: None,
+            missing_since: None,
+        }
+    }
+
+    /// Whether a final status has been reported.
+    pub(crate) fn is_done(&self) -> bool {
+        matches!(self.last, Some(TxStatus::Confirmed(_) | TxStatus::Dropped))
+    }
+
+    /// Next transition for a snapshot taken at `now`: whether the node knows
+    /// the transaction, the block it is mined in and the head. Call again
+    /// with the same snapshot until it returns `None`, since one snapshot can
+    /// both mine and confirm. A reorg that unmines the transaction goes back
+    /// to `Pending`, or starts the drop timer if the node forgot it.
+    pub(crate) fn observe(
+        &mut self,
+        known: bool,
+        mined: Option<u64>,
+        head: u64,
+        now: Instant,
+    ) -> Option<TxStatus> {
+        if self.is_done() {
+            return None;
+        }
+        let next = match mined {
+            Some(block) => {
+                self.missing_since = None;
+                if self.last != Some(TxStatus::Mined(block)) {
+                    TxStatus::Mined(block)
+                } else if head >= block.saturating_add(self.confirmations) {
+                    TxStatus::Confirmed(block)
+                } else {
+                    return None;
+                }
+            }
+            None if known => {
+                self.missing_since = None;
+                TxStatus::Pending
+            }
+            None => {
+                let since = *self.missing_since.get_or_insert(now);
+                if now.duration_since(since) < self.drop_timeout {
+                    return None;
+                }
+                TxStatus::Dropped
+            }
+        };
+        if self.last == Some(next) {
+            return None;
+        }
+        self.last = Some(next);
+        Some(next)
+    }
+}
+
+/// How far the consumer of an `EthereumClient::sync_tracked` stream got,
+/// readable after the stream is dropped.
+#[derive(Clone, Debug, Default)]
+pub struct SyncCheckpoint(Arc<Mutex<Option<u64>>>);
+
+impl SyncCheckpoint {
+    /// Last block of the newest window the consumer finished with, i.e.
+    /// asked for the next item after receiving it; `None` before the first.
+    /// A window that was yielded but not followed by another poll, because
+    /// the stream was dropped or the process stopped while handling it, does
+    /// not count, so resuming from `last + 1` neither skips nor repeats
+    /// finished windows.
+    pub fn last_processed_block(&self) -> Option<u64> {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn record(&self, block: u64) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = Some(block);
+    }
+}
+
+/// What one `fetch_event` call does, computed by [`next_window`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WindowPlan {
+    /// Inclusive block range to query; `None` when `from` is already past
+    /// `cur` and there is nothing new.
+    pub range: Option<(u64, u64)>,
+    /// `from` once the window is fetched: one past its last block.
+    pub next_from: u64,
+    /// Within `n` blocks of `cur`: wait a poll interval first and refresh
+    /// `cur` from the confirmed head afterwards.
+    pub at_tip: bool,
+}
+
+/// Next window of a sync at `from` with known confirmed head `cur` and window
+/// size `n` (0 is treated as 1). More than `n` blocks behind, the window is
+/// the `n` blocks from `from`; otherwise it runs to `cur`. The confirmation
+/// depth is already applied to `cur` and plays no part here.
+pub fn next_window(cur: u64, from: u64, n: u64) -> WindowPlan {
+    let n = n.max(1);
+    let at_tip = cur.saturating_sub(from) <= n;
+    let to = if at_tip { cur } else { from + n - 1 };
+    let range = (from <= to).then_some((from, to));
+    WindowPlan {
+        range,
+        next_from: range.map_or(from, |(_, to)| to + 1),
+        at_tip,
+    }
+}
+
+/// Consecutive inclusive ranges of at most `size` blocks covering
+/// `from..=to` in order, the requests a chunked `eth_getLogs` makes. A `size`
+/// of 0 is treated as 1. Empty when `from > to`.
+pub fn chunk_ranges(from: u64, to: u64, size: u64) -> impl Iterator<Item = (u64, u64)> {
+    let size = size.max(1);
+    let mut next = (from <= to).then_some(from);
+    std::iter::from_fn(move || {
+        let start = next?;
+        let end = to.min(start.saturating_add(size - 1));
+        next = (end < to).then(|| end + 1);
+        Some((start, end))
+    })
+}
+
+/// Snapshot of a sync's position, see [`SyncData::progress`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SyncProgress {
+    pub from: u64,
+    pub cur: u64,
+    /// `cur - from`, zero once `from` has caught up or passed `cur`.
+    pub remaining_blocks: u64,
+    /// `fetch_event` windows left to cover `remaining_blocks`.
+    pub estimated_windows: u64,
+}
+
+/// `P` is the JSON-RPC transport behind the provider; any configured
+/// `Provider<P>` can be handed in through `EthereumClient::from_provider`.
 #[derive(Clone)]
-pub struct EthereumClient {
-    pub provider: Provider<Http>,
+pub struct EthereumClient<P = Http> {
+    pub provider: Provider<P>,
     pub chain_name: String,
     pub chain_id: u64,
     pub start_block: u64,
     pub addresses: Vec<Address>,
+    pub limiter: RequestLimiter,
+    pub log_chunk_size: u64,
+    pub missing_receipts: MissingReceipts,
+    pub leaf_options: LeafOptions,
+    pub metrics: Arc<Metrics>,
+    pub confirmation_policy: ConfirmationPolicy,
+    /// Overrides `confirmation_policy` when set.
+    pub confirmation_predicate: Option<ConfirmationPredicate>,
+    pub receipt_log_fallback: bool,
+    /// Most logs one `fetch_event` window may return before it is split.
+    pub max_logs_per_window: Option<usize>,
+    /// Topic0 hashes of the events set with `with_events`, in that order.
+    pub event_topics: Vec<H256>,
+    pub poll_interval: Duration,
+}
+
+/// Everything a verifier needs to check that a transaction is part of a
+/// block's transaction tree: `root.verify(&leaf, &proof)`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TransactionInclusionProof {
+    pub block_number: u64,
+    pub transaction_index: u64,
+    pub root: MerkleTreeRoot,
+    pub proof: MerkleTreeProof,
+    pub leaf: MerkleTreeData,
+}
+
+impl TransactionInclusionProof {
+    pub fn verify(&self) -> bool {
+        self.root.verify(&self.leaf, &self.proof)
+    }
+}
+
+/// Merkle root over the transactions of the last `capacity` blocks, for
+/// time bounded commitments.
+///
+/// Blocks are pushed in ascending order and the oldest one falls out once the
+/// window is full. The tree is rebuilt on every push, so a push costs one
+/// hash per leaf in the window plus the inner nodes (O(n) for n leaves);
+/// reading the root is free.
+pub struct SlidingRootWindow {
+    capacity: usize,
+    blocks: VecDeque<(u64, Vec<MerkleTreeData>)>,
+    tree: MerkleTree,
+}
+
+impl SlidingRootWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            blocks: VecDeque::new(),
+            tree: MerkleTree::build(&[]),
+        }
+    }
+
+    /// Adds the leaves of block `number`, returning the evicted block number
+    /// when the window was full.
+    pub fn push(&mut self, number: u64, leaves: Vec<MerkleTreeData>) -> Option<u64> {
+        let evicted = if self.blocks.len() == self.capacity {
+            self.blocks.pop_front().map(|(number, _)| number)
+        } else {
+            None
+        };
+        self.blocks.push_back((number, leaves));
+
+        let leaves: Vec<MerkleTreeData> = self
+            .blocks
+            .iter()
+            .flat_map(|(_, leaves)| leaves.iter().cloned())
+            .collect();
+        self.tree = MerkleTree::build(&leaves);
+        evicted
+    }
+
+    /// `push` with the block's transaction hashes as leaves.
+    pub fn push_block(&mut self, block: &Block<H256>) -> Option<u64> {
+        let number = block.number.unwrap_or_default().as_u64();
+        let leaves = block
+            .transactions
+            .iter()
+            .map(|hash| hash.as_bytes().to_vec())
+            .collect();
+        self.push(number, leaves)
+    }
+
+    pub fn root(&self) -> &MerkleTreeRoot {
+        &self.tree.root
+    }
+
+    /// Tree over the window; leaves are in block order, then in push order.
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Oldest and newest block in the window.
+    pub fn range(&self) -> Option<(u64, u64)> {
+        Some((self.blocks.front()?.0, self.blocks.back()?.0))
+    }
+}
+
+/// Running merkle root over logs as they stream in, e.g. from `fetch_event`,
+/// without buffering them.
+///
+/// Each log becomes the leaf `rlp([address, [topic, ...], data])`, the
+/// encoding logs have inside consensus receipts, so a verifier can rebuild
+/// the root from the logs alone. Block and position fields are not part of
+/// the leaf. The root equals `MerkleTree::build` over the same leaves.
+#[derive(Clone, Debug, Default)]
+pub struct LogRootAccumulator {
+    tree: merkle::IncrementalMerkleTree,
+}
+
+impl LogRootAccumulator {
+    pub fn push(&mut self, log: &Log) {
+        self.tree.push(&log_leaf(log));
+    }
+
+    pub fn len(&self) -> u64 {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn root(&self) -> MerkleTreeRoot {
+        self.tree.root()
+    }
+}
+
+/// Leaf encoding used by [`LogRootAccumulator`].
+pub fn log_leaf(log: &Log) -> Vec<u8> {
+    let mut stream = RlpStream::new_list(3);
+    stream.append(&log.address);
+    stream.append_list(&log.topics);
+    stream.append(&log.data.as_ref());
+    stream.out().to_vec()
+}
+
+/// Size-aware anchoring payload: a block, the number of leaves in its receipt
+/// leaf tree and that tree's root, so a verifier knows the tree's shape.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct BlockAnchor {
+    pub number: u64,
+    pub leaf_count: usize,
+    pub root: MerkleTreeRoot,
 }
 
+impl BlockAnchor {
+    /// ABI encoding of `(uint256 number, uint256 leaf_count, bytes32 root)`.
+    pub fn abi_encode(&self) -> Bytes {
+        encode(&[
+            Token::Uint(self.number.into()),
+            Token::Uint(self.leaf_count.into()),
+            Token::FixedBytes(self.root.hash.to_vec()),
+        ])
+        .into()
+    }
+}
+
+/// Anchoring payload for one block: the root of the receipt leaf tree
+/// (`get_transaction_merkle`) and the root of the transaction hash tree
+/// (`get_hash_merkle`).
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
 pub struct RootParam {
     pub number: u128,
     pub root: MerkleTreeRoot,
     pub tx_root: MerkleTreeRoot,
 }
+
+impl RootParam {
+    pub fn new(
+        block: &Block<H256>,
+        root: MerkleTreeRoot,
+        tx_root: MerkleTreeRoot,
+    ) -> anyhow::Result<Self> {
+        let number = block.number.ok_or(ClientError::PendingBlock)?;
+        Ok(RootParam {
+            number: number.as_u64().into(),
+            root,
+            tx_root,
+        })
+    }
+
+    /// ABI encoding of `(uint256 number, bytes32 root, bytes32 tx_root)`, the
+    /// arguments an anchoring contract takes.
+    pub fn abi_encode(&self) -> Bytes {
+        encode(&[
+            Token::Uint(U256::from(self.number)),
+            Token::FixedBytes(self.root.hash.to_vec()),
+            Token::FixedBytes(self.tx_root.hash.to_vec()),
+        ])
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_ranges_cover_the_range_in_order() {
+        let chunks: Vec<_> = chunk_ranges(0, 25, 10).collect();
+        assert_eq!(chunks, vec![(0, 9), (10, 19), (20, 25)]);
+        assert_eq!(
+            chunk_ranges(3, 4, 0).collect::<Vec<_>>(),
+            vec![(3, 3), (4, 4)]
+        );
+        assert_eq!(chunk_ranges(5, 4, 10).count(), 0);
+        assert_eq!(
+            chunk_ranges(u64::MAX - 1, u64::MAX, 10).collect::<Vec<_>>(),
+            vec![(u64::MAX - 1, u64::MAX)]
+        );
+    }
+
+    #[test]
+    fn tx_tracker_reports_each_transition_once() {
+        let now = Instant::now();
+        let mut tracker = TxTracker::new(2, Duration::from_secs(60));
+        assert_eq!(
+            tracker.observe(true, None, 10, now),
+            Some(TxStatus::Pending)
+        );
+        assert_eq!(tracker.observe(true, None, 10, now), None);
+        assert_eq!(
+            tracker.observe(true, Some(11), 11, now),
+            Some(TxStatus::Mined(11))
+        );
+        assert_eq!(tracker.observe(true, Some(11), 12, now), None);
+
+        // reorged out and included again in another block
+        assert_eq!(
+            tracker.observe(true, None, 12, now),
+            Some(TxStatus::Pending)
+        );
+        assert_eq!(
+            tracker.observe(true, Some(12), 14, now),
+            Some(TxStatus::Mined(12))
+        );
+        assert_eq!(
+            tracker.observe(true, Some(12), 14, now),
+            Some(TxStatus::Confirmed(12))
+        );
+        assert!(tracker.is_done());
+        assert_eq!(tracker.observe(true, Some(12), 15, now), None);
+    }
+
+    #[test]
+    fn tx_tracker_drops_a_transaction_missing_for_the_timeout() {
+        let now = Instant::now();
+        let timeout = Duration::from_secs(60);
+        let mut tracker = TxTracker::new(0, timeout);
+        assert_eq!(tracker.observe(true, None, 1, now), Some(TxStatus::Pending));
+        assert_eq!(tracker.observe(false, None, 1, now), None);
+        assert_eq!(tracker.observe(false, None, 1, now + timeout / 2), None);
+        // seen again, so the timer restarts
+        assert_eq!(tracker.observe(true, None, 1, now + timeout / 2), None);
+        assert_eq!(tracker.observe(false, None, 1, now + timeout), None);
+        assert_eq!(
+            tracker.observe(false, None, 1, now + timeout * 2),
+            Some(TxStatus::Dropped)
+        );
+        assert!(tracker.is_done());
+    }
+
+    #[test]
+    fn next_window_takes_full_windows_while_behind() {
+        assert_eq!(
+            next_window(120, 10, 50),
+            WindowPlan {
+                range: Some((10, 59)),
+                next_from: 60,
+                at_tip: false,
+            }
+        );
+        assert_eq!(next_window(111, 60, 50).range, Some((60, 109)));
+    }
+
+    #[test]
+    fn next_window_runs_to_the_head_at_the_tip() {
+        // exactly `n` behind is already the tip
+        assert_eq!(
+            next_window(110, 60, 50),
+            WindowPlan {
+                range: Some((60, 110)),
+                next_from: 111,
+                at_tip: true,
+            }
+        );
+        assert_eq!(next_window(120, 120, 50).range, Some((120, 120)));
+        assert_eq!(next_window(120, 120, 50).next_from, 121);
+    }
+
+    #[test]
+    fn next_window_is_empty_once_caught_up() {
+        for (cur, from) in [(120, 121), (0, 5)] {
+            assert_eq!(
+                next_window(cur, from, 50),
+                WindowPlan {
+                    range: None,
+                    next_from: from,
+                    at_tip: true,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn next_window_windows_tile_the_chain() {
+        for n in [0, 1, 3, 50] {
+            let (head, mut from, mut covered) = (200, 7, vec![]);
+            while let Some((start, end)) = next_window(head, from, n).range {
+                assert!(end - start < n.max(1) || end == head);
+                covered.extend(start..=end);
+                from = next_window(head, from, n).next_from;
+            }
+            assert_eq!(covered, (7..=head).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn progress_rounds_windows_up_and_saturates() {
+        let sync = SyncData {
+            cur: 18_000_000,
+            from: 12_000_000,
+            n: 50_000,
+            ..SyncData::empty(0)
+        };
+        let progress = sync.progress();
+        assert_eq!(progress.remaining_blocks, 6_000_000);
+        assert_eq!(progress.estimated_windows, 120);
+
+        let partial = SyncData {
+            from: 17_999_999,
+            ..sync.clone()
+        }
+        .progress();
+        assert_eq!(partial.estimated_windows, 1);
+
+        let ahead = SyncData {
+            from: 18_000_001,
+            ..sync
+        }
+        .progress();
+        assert_eq!(ahead.remaining_blocks, 0);
+        assert_eq!(ahead.estimated_windows, 0);
+    }
+
+    #[test]
+    fn dedup_drops_logs_of_overlapping_ranges() {
+        let log = |block: u64, index: u64| Log {
+            block_hash: Some(H256::from_low_u64_be(block)),
+            log_index: Some(index.into()),
+            ..Default::default()
+        };
+        let mut dedup = LogDedup::new(16);
+
+        let first = dedup.filter(FetchedLogs::split(vec![log(1, 0), log(2, 1)]));
+        let overlap = dedup.filter(FetchedLogs::split(vec![log(2, 1), log(3, 2)]));
+        assert_eq!(first.added, vec![log(1, 0), log(2, 1)]);
+        assert_eq!(overlap.added, vec![log(3, 2)]);
+
+        let mut removed = log(3, 2);
+        removed.removed = Some(true);
+        assert_eq!(
+            dedup
+                .filter(FetchedLogs::split(vec![removed]))
+                .removed
+                .len(),
+            1
+        );
+        assert_eq!(
+            dedup
+                .filter(FetchedLogs::split(vec![log(3, 2)]))
+                .added
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn log_root_accumulates_rlp_encoded_logs() {
+        let logs: Vec<Log> = (0..5u8)
+            .map(|i| Log {
+                address: Address::repeat_byte(i),
+                topics: vec![H256::repeat_byte(i); i as usize % 3],
+                data: Bytes::from(vec![i; i as usize]),
+                ..Default::default()
+            })
+            .collect();
+
+        let mut accumulator = LogRootAccumulator::default();
+        for log in &logs {
+            accumulator.push(log);
+        }
+        let leaves: Vec<_> = logs.iter().map(log_leaf).collect();
+        assert_eq!(accumulator.root(), MerkleTree::build(&leaves).root);
+
+        // rlp([0x00.., [], ""]) for the first log
+        let mut expected = vec![0xd7, 0x94];
+        expected.extend([0u8; 20]);
+        expected.extend([0xc0, 0x80]);
+        assert_eq!(leaves[0], expected);
+    }
+
+    #[test]
+    fn sliding_window_evicts_the_oldest_block() {
+        let mut window = SlidingRootWindow::new(2);
+        assert_eq!(window.push(1, vec![vec![1]]), None);
+        assert_eq!(window.push(2, vec![vec![2], vec![3]]), None);
+        assert_eq!(window.push(3, vec![vec![4]]), Some(1));
+
+        assert_eq!(window.range(), Some((2, 3)));
+        assert_eq!(
+            *window.root(),
+            MerkleTree::build(&[vec![2], vec![3], vec![4]]).root
+        );
+    }
+
+    #[test]
+    fn event_topic0_matches_erc20_transfer() {
+        assert_eq!(
+            hex::encode(event_topic0("Transfer(address,address,uint256)")),
+            "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        );
+    }
+
+    #[test]
+    fn decode_log_with_abi_handles_hashed_and_anonymous_params() {
+        use ethers::abi::EventParam;
+
+        let param = |name: &str, kind, indexed| EventParam {
+            name: name.into(),
+            kind,
+            indexed,
+        };
+        let mut event = Event {
+            name: "Named".into(),
+            inputs: vec![
+                param("who", ParamType::Address, true),
+                param("tag", ParamType::String, true),
+                param("amount", ParamType::Uint(256), false),
+                param("note", ParamType::String, false),
+            ],
+            anonymous: false,
+        };
+        let who = Address::repeat_byte(3);
+        let tag_hash = H256(merkle::keccak256_array(b"gold"));
+        let data = encode(&[Token::Uint(9.into()), Token::String("hi".into())]);
+        let mut log = Log {
+            topics: vec![event.signature(), who.into(), tag_hash],
+            data: data.into(),
+            ..Default::default()
+        };
+
+        let expected = vec![
+            Token::Address(who),
+            Token::FixedBytes(tag_hash.as_bytes().to_vec()),
+            Token::Uint(9.into()),
+            Token::String("hi".into()),
+        ];
+        assert_eq!(decode_log_with_abi(&log, &event).unwrap(), expected);
+
+        log.topics.remove(0);
+        assert!(decode_log_with_abi(&log, &event).is_err());
+        event.anonymous = true;
+        assert_eq!(decode_log_with_abi(&log, &event).unwrap(), expected);
+    }
+
+    #[test]
+    fn leaf_carries_the_format_version() {
+        let tx = Transaction::from_receipt(&TransactionReceipt::default());
+        let leaf = tx.to_leaf().unwrap();
+
+        assert_eq!(leaf[0], LEAF_VERSION);
+        assert!(check_leaf_version(&leaf).is_ok());
+        assert!(check_leaf_version(&leaf[1..]).is_err());
+        assert!(check_leaf_version(&[]).is_err());
+    }
+
+    #[test]
+    fn rlp_leaves_are_smaller_and_change_the_root() {
+        let receipt = TransactionReceipt {
+            transaction_hash: H256::repeat_byte(1),
+            transaction_index: 3.into(),
+            logs: vec![Log::default()],
+            ..Default::default()
+        };
+        let encoder = |codec| DefaultLeafEncoder {
+            options: LeafOptions {
+                codec,
+                ..Default::default()
+            },
+        };
+        let json = encoder(LeafCodec::Json).encode(&receipt).unwrap();
+        let rlp = encoder(LeafCodec::Rlp).encode(&receipt).unwrap();
+
+        assert_eq!(json, receipt_leaf(&receipt).unwrap());
+        assert_eq!(rlp[0], LEAF_VERSION);
+        assert!(rlp.len() < json.len());
+        assert_eq!(rlp, encoder(LeafCodec::Rlp).encode(&receipt).unwrap());
+        assert_ne!(
+            MerkleTree::build(&[json]).root,
+            MerkleTree::build(&[rlp]).root
+        );
+    }
+
+    #[test]
+    fn tx_kind_is_opt_in_and_defaults_to_legacy() {
+        let receipt = TransactionReceipt {
+            transaction_type: Some(2.into()),
+            ..Default::default()
+        };
+        let plain = Transaction::from_receipt(&receipt).to_leaf().unwrap();
+        let options = LeafOptions {
+            tx_kind: true,
+            ..Default::default()
+        };
+        let tagged = Transaction::from_receipt_with(&receipt, &options)
+            .to_leaf()
+            .unwrap();
+
+        assert!(!String::from_utf8_lossy(&plain).contains("kind"));
+        assert!(String::from_utf8_lossy(&tagged).ends_with(r#","kind":"DynamicFee"}"#));
+        assert_eq!(
+            transaction_kind(&EtherTransaction::default()),
+            TxKind::Legacy
+        );
+        assert_eq!(TxKind::from_type(Some(1.into())), TxKind::AccessList);
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_leaves_are_smaller_with_a_stable_root() {
+        let receipt = TransactionReceipt {
+            transaction_hash: H256::repeat_byte(1),
+            transaction_index: 3.into(),
+            logs: vec![Log::default()],
+            ..Default::default()
+        };
+        let encode = |codec| {
+            Transaction::from_receipt(&receipt)
+                .to_leaf_with(codec)
+                .unwrap()
+        };
+        let json = encode(LeafCodec::Json);
+        let bincode = encode(LeafCodec::Bincode);
+
+        assert_eq!(bincode[0], LEAF_VERSION);
+        assert!(bincode.len() < json.len());
+        assert_eq!(bincode, encode(LeafCodec::Bincode));
+        // pins the bincode leaf format; a change here changes every such root
+        assert_eq!(
+            MerkleTree::build(&[bincode]).root.to_string(),
+            "0xc7213cf0f72ec8a0c7c1e30cbbb07e063bb0cfcba827a36b57946847d64434dc"
+        );
+    }
+
+    #[test]
+    fn block_number_binds_the_leaf_to_its_block() {
+        let receipt = |block: u64| TransactionReceipt {
+            transaction_hash: H256::repeat_byte(1),
+            block_hash: Some(H256::repeat_byte(2)),
+            block_number: Some(block.into()),
+            ..Default::default()
+        };
+        let options = LeafOptions {
+            block_number: true,
+            ..Default::default()
+        };
+        let leaf = |block, codec| {
+            Transaction::from_receipt_with(&receipt(block), &options)
+                .to_leaf_with(codec)
+                .unwrap()
+        };
+
+        assert_eq!(
+            receipt_leaf(&receipt(7)).unwrap(),
+            receipt_leaf(&receipt(8)).unwrap()
+        );
+        assert!(
+            String::from_utf8_lossy(&leaf(7, LeafCodec::Json)).ends_with(r#","block_number":7}"#)
+        );
+        assert_ne!(leaf(7, LeafCodec::Rlp), leaf(8, LeafCodec::Rlp));
+        // pins the leaf format; a change here changes every such root
+        assert_eq!(
+            merkle::hash_to_hex(&MerkleTree::build(&[leaf(7, LeafCodec::Json)]).root.hash),
+            "0x70f40fb1b8f5e4dc066fea6c1800867a68a5f2cf959861457120dabe5c8a1a01"
+        );
+    }
+
+    #[test]
+    fn fetched_logs_split_on_the_removed_flag() {
+        let log = |index: u64, removed: Option<bool>| Log {
+            log_index: Some(index.into()),
+            removed,
+            ..Default::default()
+        };
+        let fetched =
+            FetchedLogs::split(vec![log(0, None), log(1, Some(true)), log(2, Some(false))]);
+
+        assert_eq!(fetched.added, vec![log(0, None), log(2, Some(false))]);
+        assert_eq!(fetched.removed, vec![log(1, Some(true))]);
+    }
+
+    #[test]
+    fn receipt_leaf_matches_the_client_tree() {
+        let receipt = TransactionReceipt {
+            transaction_hash: H256::repeat_byte(4),
+            ..Default::default()
+        };
+        let leaf = receipt_leaf(&receipt).unwrap();
+        let tree = merkle::MerkleTree::build(std::slice::from_ref(&leaf));
+
+        assert_eq!(leaf, Transaction::from_receipt(&receipt).to_leaf().unwrap());
+        assert_eq!(tree.leaf_hashes(), &[receipt_leaf_hash(&receipt)]);
+    }
+
+    #[test]
+    fn root_param_encodes_for_the_anchoring_contract() {
+        let block = Block::<H256> {
+            number: Some(7.into()),
+            ..Default::default()
+        };
+        let param = RootParam::new(
+            &block,
+            MerkleTreeRoot { hash: [1; 32] },
+            MerkleTreeRoot { hash: [2; 32] },
+        )
+        .unwrap();
+        let encoded = param.abi_encode();
+
+        assert_eq!(encoded.len(), 96);
+        assert_eq!(encoded[31], 7);
+        assert_eq!(&encoded[32..64], &[1; 32]);
+        assert_eq!(&encoded[64..], &[2; 32]);
+        assert!(RootParam::new(&Block::default(), param.root.clone(), param.tx_root).is_err());
+    }
+
+    #[tokio::test]
+    async fn limiter_counts_in_flight_requests() {
+        let limiter = RequestLimiter::new(2);
+        assert_eq!(limiter.in_flight(), 0);
+
+        let seen = limiter.run(async { limiter.in_flight() }).await;
+        assert_eq!(seen, 1);
+        assert_eq!(limiter.in_flight(), 0);
+        assert_eq!(RequestLimiter::new(0).max(), 1);
+    }
+}
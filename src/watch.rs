@@ -0,0 +1,416 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use ethers::abi::RawLog;
+use ethers::prelude::*;
+use ethers::providers::{Provider, Ws};
+use ethers::types::{FilterBlockOption, Log};
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::stream::{self, Stream, StreamExt};
+use serde_json::value::RawValue;
+
+use crate::types::{chunk_ranges, EthereumClient, LogKey, DEFAULT_LOG_CHUNK_SIZE};
+
+/// Delay between reconnect attempts after the socket drops.
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Blocks below the newest one whose hashes a [`LogCursor`] remembers to
+/// tell a reorg from a replay.
+pub const REORG_WINDOW: u64 = 128;
+
+/// Tracks what a watch has already emitted so a backfill after a reconnect
+/// neither drops nor repeats logs.
+///
+/// Logs arrive in block order, so every block below `last_block` has been
+/// emitted completely; only `last_block` itself may be partial and is
+/// deduplicated by `(block_hash, log_index)`. The hashes of the blocks logs
+/// came from are kept for [`REORG_WINDOW`] blocks: a log from a block at or
+/// below `last_block` with another hash than the one emitted for that number
+/// belongs to a new canonical chain and rewinds the cursor to that block, as
+/// does a `removed: true` log.
+#[derive(Clone, Debug, Default)]
+pub struct LogCursor {
+    // logs below the block the watch started at are never emitted
+    start: Option<u64>,
+    last_block: Option<u64>,
+    seen: HashSet<LogKey>,
+    hashes: BTreeMap<u64, H256>,
+}
+
+impl LogCursor {
+    pub fn new(from_block: Option<u64>) -> Self {
+        Self {
+            start: from_block,
+            last_block: from_block,
+            seen: HashSet::new(),
+            hashes: BTreeMap::new(),
+        }
+    }
+
+    /// Inclusive block a backfill has to start from.
+    pub fn resume_block(&self) -> Option<u64> {
+        self.last_block
+    }
+
+    /// Returns whether `log` is new and records it. Reorg removals
+    /// (`removed: true`) always pass so callers can undo them, and rewind the
+    /// cursor so the replacement logs of that block and later ones pass too.
+    pub fn accept(&mut self, log: &Log) -> bool {
+        if log.removed == Some(true) {
+            if let Some(number) = log.block_number.map(|number| number.as_u64()) {
+                if self.start.is_some_and(|start| number >= start) {
+                    self.rewind(number);
+                }
+            }
+            return true;
+        }
+        let (Some(number), Some(hash), Some(index)) =
+            (log.block_number, log.block_hash, log.log_index)
+        else {
+            return true;
+        };
+        let number = number.as_u64();
+        if number < *self.start.get_or_insert(number) {
+            return false;
+        }
+
+        if let Some(last) = self.last_block.filter(|last| number <= *last) {
+            match self.hashes.get(&number) {
+                Some(known) if *known == hash => {
+                    return number == last && self.seen.insert((hash, index))
+                }
+                // older than anything we could tell apart from a replay
+                None if number < last.saturating_sub(REORG_WINDOW) => return false,
+                // a block we emitted nothing for, or another chain's block
+                _ if number < last || self.hashes.contains_key(&number) => self.rewind(number),
+                _ => {}
+            }
+        }
+        self.last_block = Some(number);
+        self.hashes.insert(number, hash);
+        self.hashes = self.hashes.split_off(&number.saturating_sub(REORG_WINDOW));
+        self.seen.insert((hash, index))
+    }
+
+    /// Forgets everything emitted from block `number` on.
+    fn rewind(&mut self, number: u64) {
+        self.hashes.split_off(&number);
+        self.seen.clear();
+        self.last_block = Some(self.last_block.map_or(number, |last| last.min(number)));
+    }
+}
+
+/// Where a [`Watch`] gets its socket from.
+enum Source<'a> {
+    /// Dials the endpoint again on every reconnect.
+    Url(String),
+    /// Resubscribes on the client's provider, whose transport reconnects
+    /// itself; requests go through the client's limiter and metrics.
+    Client(&'a EthereumClient<Ws>),
+}
+
+struct Watch<'a> {
+    source: Source<'a>,
+    filter: Filter,
+    cursor: LogCursor,
+    live: Option<(Provider<Ws>, UnboundedReceiver<Box<RawValue>>)>,
+    backlog: VecDeque<Log>,
+    reconnecting: bool,
+}
+
+impl<'a> Watch<'a> {
+    async fn connect(&mut self) -> anyhow::Result<()> {
+        let provider = match &self.source {
+            Source::Url(url) => Provider::<Ws>::connect(url.as_str()).await?,
+            Source::Client(client) => client.provider.clone(),
+        };
+
+        // subscribe before backfilling so nothing mined in between is lost;
+        // the overlap is removed by the cursor
+        let mut live_filter = self.filter.clone();
+        live_filter.block_option = FilterBlockOption::default();
+        let subscribe = provider.request::<_, U256>("eth_subscribe", ("logs", &live_filter));
+        let id = match &self.source {
+            Source::Url(_) => subscribe.await?,
+            Source::Client(client) => client
+                .observe("eth_subscribe", subscribe)
+                .await
+                .map_err(|err| client.chain_error(err))?,
+        };
+        let notifications = provider.as_ref().subscribe(id)?;
+
+        let head = match &self.source {
+            Source::Url(_) => provider.get_block_number().await?.as_u64(),
+            Source::Client(client) => client.limiter.run(client.get_block_count()).await?,
+        };
+        match self.cursor.resume_block() {
+            Some(from) if from <= head => {
+                let range = self.filter.clone().from_block(from).to_block(head);
+                for log in self.backfill(&provider, range).await? {
+                    if self.cursor.accept(&log) {
+                        self.backlog.push_back(log);
+                    }
+                }
+            }
+            Some(_) => {}
+            None => self.cursor = LogCursor::new(Some(head)),
+        }
+
+        self.live = Some((provider, notifications));
+        Ok(())
+    }
+
+    /// Logs of the numeric `range`, in [`DEFAULT_LOG_CHUNK_SIZE`] requests or
+    /// through the client's `get_logs_filtered`.
+    async fn backfill(&self, provider: &Provider<Ws>, range: Filter) -> anyhow::Result<Vec<Log>> {
+        if let Source::Client(client) = &self.source {
+            return client.limiter.run(client.get_logs_filtered(range)).await;
+        }
+        let (Some(from), Some(to)) = (range.get_from_block(), range.get_to_block()) else {
+            return Ok(provider.get_logs(&range).await?);
+        };
+        let mut logs = Vec::new();
+        for (start, end) in chunk_ranges(from.as_u64(), to.as_u64(), DEFAULT_LOG_CHUNK_SIZE) {
+            logs.extend(
+                provider
+                    .get_logs(&range.clone().from_block(start).to_block(end))
+                    .await?,
+            );
+        }
+        Ok(logs)
+    }
+
+    fn stream(self) -> impl Stream<Item = anyhow::Result<Log>> + 'a {
+        stream::unfold(self, |mut watch| async move {
+            loop {
+                if let Some(log) = watch.backlog.pop_front() {
+                    return Some((Ok(log), watch));
+                }
+
+                let Some((provider, mut notifications)) = watch.live.take() else {
+                    if watch.reconnecting {
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                    watch.reconnecting = true;
+                    if let Err(err) = watch.connect().await {
+                        return Some((Err(err), watch));
+                    }
+                    continue;
+                };
+
+                // `None` means the socket went away; loop around to reconnect
+                if let Some(raw) = notifications.next().await {
+                    watch.live = Some((provider, notifications));
+                    match serde_json::from_str::<Log>(raw.get()) {
+                        Ok(log) if watch.cursor.accept(&log) => return Some((Ok(log), watch)),
+                        Ok(_) => {}
+                        Err(err) => return Some((Err(err.into()), watch)),
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Stream of logs matching `filter` over the WebSocket endpoint `url`.
+///
+/// With `from_block` the stream first backfills from that block, otherwise it
+/// starts at the current head. When the socket drops it reconnects, backfills
+/// the gap with `eth_getLogs` from the last seen block, in
+/// [`DEFAULT_LOG_CHUNK_SIZE`] wide requests, and continues live. Reorgs are
+/// followed as described on [`LogCursor`].
+/// Connection failures are yielded as errors and retried every
+/// [`RECONNECT_DELAY`]; drop the stream to stop watching.
+pub fn watch_logs(
+    url: &str,
+    filter: Filter,
+    from_block: Option<u64>,
+) -> impl Stream<Item = anyhow::Result<Log>> {
+    Watch {
+        source: Source::Url(url.to_owned()),
+        filter,
+        cursor: LogCursor::new(from_block),
+        live: None,
+        backlog: VecDeque::new(),
+        reconnecting: false,
+    }
+    .stream()
+}
+
+impl EthereumClient<Ws> {
+    /// [`watch_logs`] over the client's own WebSocket provider. Backfills go
+    /// through [`EthereumClient::get_logs_filtered`], so they are chunked by
+    /// `log_chunk_size`, use the receipt fallback and show up in the
+    /// metrics. When the subscription ends it is renewed on the provider
+    /// and the gap is backfilled. A reconnect the provider's transport does
+    /// on its own resubscribes without telling the stream, so logs mined
+    /// while that socket was down are only caught by the free
+    /// [`watch_logs`], which dials the endpoint itself.
+    pub fn watch_logs(
+        &self,
+        filter: Filter,
+        from_block: Option<u64>,
+    ) -> impl Stream<Item = anyhow::Result<Log>> + '_ {
+        Watch {
+            source: Source::Client(self),
+            filter,
+            cursor: LogCursor::new(from_block),
+            live: None,
+            backlog: VecDeque::new(),
+            reconnecting: false,
+        }
+        .stream()
+    }
+
+    /// [`EthereumClient::watch_logs`] for the `E` events of the contract at
+    /// `address`, each decoded and paired with its raw log.
+    ///
+    /// Backfills from `from_block`, then follows the head. Logs undone by a
+    /// reorg come through with `removed: true` on the raw log and should be
+    /// reverted by the caller; the replacement logs follow.
+    pub fn watch_contract<E: EthEvent>(
+        &self,
+        address: Address,
+        from_block: u64,
+    ) -> impl Stream<Item = anyhow::Result<(E, Log)>> + '_ {
+        let filter = Filter::new().address(address).topic0(E::signature());
+        self.watch_logs(filter, Some(from_block))
+            .map(|log| decode_event(log?))
+    }
+}
+
+fn decode_event<E: EthEvent>(log: Log) -> anyhow::Result<(E, Log)> {
+    let event = E::decode_log(&RawLog::from(log.clone()))?;
+    Ok((event, log))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(block: u64, index: u64) -> Log {
+        Log {
+            block_number: Some(block.into()),
+            block_hash: Some(H256::from_low_u64_be(block)),
+            log_index: Some(index.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn backfill_after_reconnect_resumes_without_gaps_or_repeats() {
+        let mut cursor = LogCursor::new(Some(10));
+        let live: Vec<_> = [log(10, 0), log(11, 1)]
+            .iter()
+            .filter(|l| cursor.accept(l))
+            .cloned()
+            .collect();
+        assert_eq!(live.len(), 2);
+        assert_eq!(cursor.resume_block(), Some(11));
+
+        // socket dropped after the first log of block 11
+        let backfill = [log(11, 1), log(11, 2), log(12, 0)];
+        let resumed: Vec<_> = backfill
+            .iter()
+            .filter(|l| cursor.accept(l))
+            .cloned()
+            .collect();
+        assert_eq!(resumed, vec![log(11, 2), log(12, 0)]);
+
+        // the live subscription replays what the backfill already covered
+        assert!(!cursor.accept(&log(11, 2)));
+        assert!(!cursor.accept(&log(12, 0)));
+        assert!(cursor.accept(&log(13, 0)));
+    }
+
+    #[derive(Clone, Debug, PartialEq, EthEvent)]
+    struct Transfer {
+        #[ethevent(indexed)]
+        from: Address,
+        #[ethevent(indexed)]
+        to: Address,
+        value: U256,
+    }
+
+    #[test]
+    fn decode_event_keeps_the_raw_log() {
+        let from = Address::repeat_byte(1);
+        let to = Address::repeat_byte(2);
+        let raw = Log {
+            topics: vec![Transfer::signature(), from.into(), to.into()],
+            data: ethers::abi::encode(&[ethers::abi::Token::Uint(5.into())]).into(),
+            removed: Some(true),
+            ..log(3, 0)
+        };
+
+        let (event, log) = decode_event::<Transfer>(raw.clone()).unwrap();
+        assert_eq!(
+            event,
+            Transfer {
+                from,
+                to,
+                value: 5.into()
+            }
+        );
+        assert_eq!(log, raw);
+
+        let mut other = raw;
+        other.topics[0] = H256::zero();
+        assert!(decode_event::<Transfer>(other).is_err());
+    }
+
+    fn reorged(block: u64, index: u64) -> Log {
+        Log {
+            block_hash: Some(H256::from_low_u64_be(block + 100)),
+            ..log(block, index)
+        }
+    }
+
+    #[test]
+    fn a_two_block_reorg_lets_the_new_chain_through() {
+        let mut cursor = LogCursor::new(Some(10));
+        for l in [log(10, 0), log(11, 0), log(12, 0)] {
+            assert!(cursor.accept(&l));
+        }
+
+        // the node reports the old logs of 11 and 12 as removed
+        for l in [log(12, 0), log(11, 0)] {
+            assert!(cursor.accept(&Log {
+                removed: Some(true),
+                ..l
+            }));
+        }
+        assert_eq!(cursor.resume_block(), Some(11));
+        assert!(cursor.accept(&reorged(11, 0)));
+        assert!(cursor.accept(&reorged(12, 0)));
+        assert!(!cursor.accept(&reorged(11, 0)));
+        assert!(!cursor.accept(&reorged(12, 0)));
+        assert!(!cursor.accept(&log(10, 0)));
+    }
+
+    #[test]
+    fn a_reorg_seen_only_as_new_hashes_rewinds_the_cursor() {
+        // e.g. a backfill after a reconnect, without removal notices
+        let mut cursor = LogCursor::new(Some(10));
+        for l in [log(10, 0), log(11, 0), log(12, 0)] {
+            assert!(cursor.accept(&l));
+        }
+
+        assert!(cursor.accept(&reorged(11, 0)));
+        assert_eq!(cursor.resume_block(), Some(11));
+        assert!(cursor.accept(&reorged(12, 1)));
+        assert!(!cursor.accept(&reorged(12, 1)));
+        assert!(!cursor.accept(&log(10, 0)));
+        assert!(!cursor.accept(&log(9, 0)));
+    }
+
+    #[test]
+    fn removed_logs_always_pass() {
+        let mut cursor = LogCursor::new(Some(20));
+        let mut removed = log(15, 0);
+        removed.removed = Some(true);
+
+        assert!(!cursor.accept(&log(15, 0)));
+        assert!(cursor.accept(&removed));
+    }
+}
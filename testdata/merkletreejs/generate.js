@@ -0,0 +1,24 @@
+// Writes marshal.json, the merkletreejs side of the
+// `merkletreejs_fixture_matches_the_export` test in src/merkle.rs.
+//
+//   npm install merkletreejs@0.3 keccak256@1
+//   node generate.js
+//
+// The tree is configured as documented on `MerkleTree::to_merkletreejs_json`,
+// over the leaves keccak256([0]) .. keccak256([4]).
+const fs = require('fs');
+const path = require('path');
+const { MerkleTree } = require('merkletreejs');
+const keccak256 = require('keccak256');
+
+const leaves = [0, 1, 2, 3, 4].map((i) => keccak256(Buffer.from([i])));
+const hashFn = (b) =>
+  keccak256(Buffer.from(JSON.stringify([[...b.subarray(0, 32)], [...b.subarray(32)]])));
+
+const tree = new MerkleTree(leaves, hashFn, {
+  hashLeaves: false,
+  sortPairs: true,
+  fillDefaultHash: () => keccak256(Buffer.alloc(0)),
+});
+
+fs.writeFileSync(path.join(__dirname, 'marshal.json'), MerkleTree.marshalTree(tree) + '\n');